@@ -18,10 +18,21 @@ function innerField(json, fields) {
   return curObj;
 }
 
+// Returns the display name of the player with the given index, falling back to
+// their seat number.
+function playerName(json, playerIndex) {
+  const names = innerField(json, ['history', 'lobby_history', 'player_names']);
+  if (names && names[playerIndex]) {
+    return names[playerIndex];
+  }
+
+  return 'Player ' + (playerIndex + 1);
+}
+
 // Takes in a card JSON struct and returns a nicer string representation.
 function prettyCard(cardJson) {
-  const FACES = [null, null, null, null, 4, 5, 6,
-    7, 8, 9, 10, 'J', 'Q', 'K', 'A',
+  const FACES = [null, null, 2, 3, 4, 5, 6,
+    7, 8, 9, 10, 11, 12, 13, 'J', 'Q', 'K', 'A',
   ];
   const SUITS = {
     'Spades': '♠',
@@ -104,19 +115,19 @@ function uglyCard(card) {
   };
 
   const FACES = {
-    'J': 11,
-    'Q': 12,
-    'K': 13,
-    'A': 14,
+    'J': 14,
+    'Q': 15,
+    'K': 16,
+    'A': 17,
   };
 
   if (card == '★') {
     return 'Joker';
   }
 
-  // Only 2 char face is "10".
-  const face = card[1] == '0' ? '10' : card[0];
-  const suit = card[1] == '0' ? card[2] : card[1];
+  // Faces can be 1 or 2 chars (e.g. "10"), and suits are always 1 char.
+  const face = card.slice(0, -1);
+  const suit = card.slice(-1);
   return {
     'SuitedCard': {
       'face': FACES[face] ?? parseInt(face),
@@ -125,6 +136,18 @@ function uglyCard(card) {
   };
 }
 
+// Convert card string (and the suit to assign a joker) into the play json
+// struct expected by the server.
+function uglyPlay(card, jokerSuit) {
+  if (card == '★') {
+    return {
+      'Joker': jokerSuit,
+    };
+  }
+
+  return uglyCard(card);
+}
+
 // Helper: create a new option for a select element that contains the given
 // text.
 function newSelectOption(contents) {
@@ -184,7 +207,8 @@ function insertCardPicker(e) {
   const face = document.createElement('select');
   face.disabled = true;
   e.appendChild(face);
-  for (const c of ['4', '5', '6', '7', '8', '9', '10', 'J', 'Q', 'K', 'A']) {
+  for (const c of ['2', '3', '4', '5', '6', '7', '8', '9', '10', '11', '12', '13', 'J',
+    'Q', 'K', 'A']) {
     face.add(newSelectOption(c));
   }
 
@@ -220,7 +244,9 @@ function updateStage(json) {
   stage.innerHTML = '';
 
   switch (json['state']) {
+    case 'RoomJoined':
     case 'PlayerJoined':
+    case 'LobbyUpdated':
       stage.innerHTML = 'Lobby';
       break;
 
@@ -234,12 +260,27 @@ function updateStage(json) {
       stage.innerHTML = 'Waiting for kitty';
       break;
 
+    case 'WaitingForTheirPartnerCall':
+    case 'WaitingForYourPartnerCall':
+      stage.innerHTML = 'Waiting for partner call';
+      break;
+
     case 'WaitingForTheirPlay':
     case 'WaitingForYourPlay':
-      // TODO: update this to use info in history struct.
+    case 'JokerSuitAnnounced':
+    case 'TrickWon':
       stage.innerHTML = 'Playing';
       break;
 
+    case 'GameWon':
+    case 'ScoresUpdated':
+      stage.innerHTML = 'Game over';
+      break;
+
+    case 'MatchWon':
+      stage.innerHTML = 'Match over';
+      break;
+
     case 'Error':
       // Display in red.
       stage.innerHTML = '<div style=\'color: red\'>Error</div>';
@@ -270,8 +311,30 @@ function updateInfo(json) {
   }
 
   switch (json['state']) {
+    case 'RoomJoined':
+      info.innerHTML = 'Joined room ' + json['history']['room_code'];
+      break;
+
+    case 'Spectating':
+      info.innerHTML = 'Spectating the match';
+      break;
+
     case 'PlayerJoined':
-      info.innerHTML = 'Waiting for other players to join';
+    case 'LobbyUpdated': {
+      const lobbyHistory = json['history']['lobby_history'];
+      const readyCount = lobbyHistory['players_ready'].filter((r) => r).length;
+      info.innerHTML =
+        'Waiting for players to join and ready up (' + readyCount + ' of ' +
+        lobbyHistory['player_count'] + ' joined players ready)';
+      break;
+    }
+
+    case 'PlayerDisconnected':
+      info.innerHTML = 'Waiting for disconnected players to return';
+      break;
+
+    case 'PlayerReconnected':
+      info.innerHTML = 'A disconnected player has returned';
       break;
 
     case 'WaitingForYourBid':
@@ -280,25 +343,94 @@ function updateInfo(json) {
 
     case 'WaitingForTheirBid':
       info.innerHTML =
-        'Waiting for player ' +
-        (json['history']['game_history']['bidding_history']['current_bidder_index'] + 1) +
+        'Waiting for ' +
+        playerName(json, json['history']['game_history']['bidding_history']['current_bidder_index']) +
         ' to bid';
       break;
 
+    case 'PlayerBid': {
+      // The bidder is still the current bidder when their bid is broadcast.
+      const biddingHistory = json['history']['game_history']['bidding_history'];
+      const bidderIndex = biddingHistory['current_bidder_index'];
+      info.innerHTML =
+        playerName(json, bidderIndex) + ' bid ' + prettyBid(biddingHistory['bids'][bidderIndex]);
+      break;
+    }
+
+    case 'AllPassed':
+      info.innerHTML =
+        json['history']['game_history']['bidding_history']['all_passed'] === 'Redeal' ?
+        'Everyone passed: redealing' :
+        'Everyone passed: playing at no trumps';
+      break;
+
     case 'WaitingForYourKitty':
       info.innerHTML = 'Use the kitty';
       break;
 
     case 'WaitingForTheirKitty':
       info.innerHTML =
-        'Waiting for player ' +
-        (json['history']['game_history']['winning_bid_history']['winning_bidder_index'] + 1) +
+        'Waiting for ' +
+        playerName(json, json['history']['game_history']['winning_bid_history']['winning_bidder_index']) +
         ' to use the kitty';
       break;
 
+    case 'WaitingForYourPartnerCall':
+      info.innerHTML = 'Name a card to call your partner';
+      break;
+
+    case 'WaitingForTheirPartnerCall':
+      info.innerHTML = 'Waiting for a partner to be called';
+      break;
+
+    case 'PartnerCalled':
+      info.innerHTML =
+        'The holder of ' +
+        prettyCard(json['history']['game_history']['winning_bid_history']['called_card']) +
+        ' is the partner';
+      break;
+
+    case 'WaitingForYourPlay':
+      info.innerHTML = 'Play a card';
+      break;
+
     case 'WaitingForTheirPlay':
-      // TODO: update to use info in history struct.
-      info.innerHTML = 'Waiting for player to play';
+      info.innerHTML =
+        'Waiting for ' +
+        playerName(json, json['history']['game_history']['plays_history']['currently_playing_player_index']) +
+        ' to play';
+      break;
+
+    case 'JokerSuitAnnounced':
+      info.innerHTML =
+        'The joker was nominated as ' +
+        json['history']['game_history']['plays_history']['joker_suit'];
+      break;
+
+    case 'TrickWon':
+      info.innerHTML =
+        playerName(json, json['history']['game_history']['plays_history']['previous_trick_winner']) +
+        ' won the trick';
+      break;
+
+    case 'GameWon': {
+      const games = json['history']['match_history']['past_games'];
+      info.innerHTML =
+        'Team ' + (games[games.length - 1]['winning_team_index'] + 1) + ' won the game' +
+        (games[games.length - 1]['slam'] ? ' with a slam' : '');
+      break;
+    }
+
+    case 'ScoresUpdated': {
+      const games = json['history']['match_history']['past_games'];
+      info.innerHTML = 'Scores: ' + games[games.length - 1]['score_totals'].join(' - ');
+      break;
+    }
+
+    case 'MatchWon':
+      info.innerHTML =
+        'Team ' + (json['history']['match_history']['winning_team_index'] + 1) +
+        ' won the match';
       break;
 
     case 'Excluded':
@@ -330,9 +462,20 @@ function updatePlayerNames(json) {
   const playerIndex = lobbyHistory['your_player_index'];
 
   for (let i = 0; i < playerCount; ++i) {
-    const index = (i - playerIndex + 4) % 4;
+    // The surface only has room for four players.
+    const index = (i - playerIndex + playerCount) % playerCount;
+    if (index >= PLAYER_PREFIXES.length) {
+      continue;
+    }
+
     const e = document.getElementById(PLAYER_PREFIXES[index] + '_name');
-    e.innerHTML = 'Player ' + (i + 1);
+    e.innerHTML = playerName(json, i);
+    if (lobbyHistory['players_ready'] && lobbyHistory['players_ready'][i]) {
+      e.innerHTML += ' (ready)';
+    }
+    if (lobbyHistory['swap_requests'] && lobbyHistory['swap_requests'].includes(i)) {
+      e.innerHTML += ' (wants to swap)';
+    }
     e.classList.remove('greyed');
 
     if (i == playerIndex) {
@@ -376,6 +519,48 @@ function updateCards(json) {
   }
 }
 
+// Updates the chat log to show the recent messages sent by the server.
+function updateChat(json) {
+  const REACTIONS = {
+    'Nice': '👍 Nice!',
+    'Oops': '😬 Oops!',
+    'Gg': '🤝 Good game!',
+  };
+
+  const chat = document.getElementById('chat');
+  chat.innerHTML = '';
+
+  for (const message of innerField(json, ['history', 'recent_chat']) ?? []) {
+    const line = document.createElement('div');
+    const content = message['content'];
+    line.textContent = message['name'] + ': ' +
+        (content['Text'] ?? REACTIONS[content['Reaction']]);
+    chat.appendChild(line);
+  }
+}
+
+// The time at which the current turn runs out, in milliseconds since the epoch,
+// or null if the turn isn't timed.
+let turnDeadline = null;
+
+// Records the deadline of the current turn sent by the server.
+function updateCountdown(json) {
+  turnDeadline = innerField(json, ['history', 'game_history', 'turn_deadline']);
+  renderCountdown();
+}
+
+// Shows the seconds left in the current turn.
+function renderCountdown() {
+  const countdown = document.getElementById('countdown');
+  if (turnDeadline === null) {
+    countdown.innerHTML = '';
+    return;
+  }
+
+  const secondsLeft = Math.max(0, Math.ceil((turnDeadline - Date.now()) / 1000));
+  countdown.innerHTML = secondsLeft + 's left in this turn';
+}
+
 function updateAuxUi(json) {
   // Clear old info.
   const bids = document.getElementById('bids');
@@ -439,6 +624,9 @@ function main() {
     insertCardPicker(e);
   }
 
+  // Tick down the turn timer.
+  setInterval(renderCountdown, 250);
+
   // Connect to the server.
   const socket = new WebSocket('ws://192.168.1.69:8080');
 
@@ -493,6 +681,8 @@ function main() {
     updateInfo(json);
     updatePlayerNames(json);
     updateCards(json);
+    updateChat(json);
+    updateCountdown(json);
     updateAuxUi(json);
 
     // Add new response to top of state log.
@@ -509,10 +699,100 @@ function main() {
 
   // Step UI logic.
 
+  // Send CreateRoom step. Without rules, the room uses the server's.
+  document.getElementById('create_room_button').addEventListener('click', () => {
+    const rules = document.getElementById('room_rules').value;
+    const payload = {
+      'CreateRoom': rules ? JSON.parse(rules) : null,
+    };
+    socket.send(JSON.stringify(payload));
+  });
+
+  // Send JoinRoom step.
+  document.getElementById('join_room_button').addEventListener('click', () => {
+    const payload = {
+      'JoinRoom': document.getElementById('room_code').value,
+    };
+    socket.send(JSON.stringify(payload));
+  });
+
   // Send Join step.
   document.getElementById('join_button').addEventListener('click', () => {
     const payload = {
-      'Join': parseInt(document.getElementById('join_team').value),
+      'Join': [
+        parseInt(document.getElementById('join_team').value),
+        document.getElementById('join_name').value,
+      ],
+    };
+    socket.send(JSON.stringify(payload));
+  });
+
+  // Send Ready step.
+  document.getElementById('ready_button').addEventListener('click', () => {
+    const payload = {
+      'Ready': document.getElementById('ready').checked,
+    };
+    socket.send(JSON.stringify(payload));
+  });
+
+  // Send ChangeTeam step.
+  document.getElementById('change_team_button').addEventListener('click', () => {
+    const payload = {
+      'ChangeTeam': parseInt(document.getElementById('join_team').value),
+    };
+    socket.send(JSON.stringify(payload));
+  });
+
+  // Send AddBot step.
+  document.getElementById('add_bot_button').addEventListener('click', () => {
+    const payload = {
+      'AddBot': [
+        parseInt(document.getElementById('bot_team').value),
+        document.getElementById('bot_difficulty').value,
+      ],
+    };
+    socket.send(JSON.stringify(payload));
+  });
+
+  // Send SwapSeats step.
+  document.getElementById('swap_seats_button').addEventListener('click', () => {
+    const payload = {
+      'SwapSeats': parseInt(document.getElementById('swap_seat').value) - 1,
+    };
+    socket.send(JSON.stringify(payload));
+  });
+
+  // Send Quit step.
+  document.getElementById('quit_button').addEventListener('click', () => {
+    socket.send(JSON.stringify('Quit'));
+  });
+
+  // Send Spectate step.
+  document.getElementById('spectate_button').addEventListener('click', () => {
+    socket.send(JSON.stringify('Spectate'));
+  });
+
+  // Send Chat step.
+  document.getElementById('chat_button').addEventListener('click', () => {
+    const payload = {
+      'Chat': document.getElementById('chat_text').value,
+    };
+    socket.send(JSON.stringify(payload));
+    document.getElementById('chat_text').value = '';
+  });
+
+  // Send React step.
+  document.getElementById('react_button').addEventListener('click', () => {
+    const payload = {
+      'React': document.getElementById('reaction').value,
+    };
+    socket.send(JSON.stringify(payload));
+  });
+
+  // Send Resume step.
+  document.getElementById('resume_button').addEventListener('click', () => {
+    const payload = {
+      'Resume': document.getElementById('resume_token').value,
     };
     socket.send(JSON.stringify(payload));
   });
@@ -536,6 +816,24 @@ function main() {
     };
     socket.send(JSON.stringify(payload));
   });
+
+  // Send CallPartner step.
+  document.getElementById('call_partner_button').addEventListener('click', () => {
+    const payload = {
+      'CallPartner': uglyCard(document.getElementById('called_card').card),
+    };
+    socket.send(JSON.stringify(payload));
+  });
+
+  // Send MakePlay step.
+  document.getElementById('play_button').addEventListener('click', () => {
+    const payload = {
+      'MakePlay': uglyPlay(
+          document.getElementById('picked_play').card,
+          document.getElementById('play_joker_suit').value),
+    };
+    socket.send(JSON.stringify(payload));
+  });
 }
 
 main();
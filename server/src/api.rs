@@ -10,8 +10,41 @@ pub enum Step {
     // Degenerate case: just ask to see state.
     Poll,
 
-    // Ask to join.
-    Join(usize), // The index of the team to join (i.e. in [0, 1]).
+    // Open a new room played under the given house rules (or the server's, if none are given), and
+    // enter it.
+    CreateRoom(Option<types::Rules>),
+
+    // Enter the room with the given code.
+    JoinRoom(String),
+
+    // Ask to join, with the index of the team to join (e.g. in [0, 1] for four
+    // players) and your display name. Without fixed partnerships, this is the
+    // seat you will sit in.
+    Join(usize, String),
+
+    // Say whether you are ready for the match to start. The first hand is
+    // dealt once the table is full and every player is ready.
+    Ready(bool),
+
+    // Move to the team with the given index, if it has a seat free.
+    ChangeTeam(usize),
+
+    // Ask to swap seats (and teams) with the player with the given index. The
+    // seats are swapped once they ask to swap with you too.
+    SwapSeats(usize),
+
+    // Seat a computer player of the given difficulty on the team with the
+    // given index, if it has a seat free. Only players in the lobby can add
+    // bots.
+    AddBot(usize, types::BotDifficulty),
+
+    // Follow the match without playing in it. You will be sent what every
+    // player can see, but no player's private cards.
+    Spectate,
+
+    // Take back your seat after your connection has dropped, using the resume
+    // token you were given when you joined.
+    Resume(String),
 
     // Make a bid.
     MakeBid(types::Bid),
@@ -19,13 +52,19 @@ pub enum Step {
     // Discard cards after you have received the kitty.
     DiscardCards(Vec<types::Card>), // Invariant: length of 3.
 
-    // Announce the suit of the joker in your hand.
-    AnnounceJokerSuit(types::Suit),
+    // Name a card to call the player holding it as your partner.
+    CallPartner(types::Card),
 
     // Choose a card (and possibly the suit of the joker) to play.
     MakePlay(types::Play),
 
-    // Exit the match early.
+    // Send a chat message to the table.
+    Chat(String),
+
+    // Send a quick reaction to the table.
+    React(types::Reaction),
+
+    // Leave the lobby, or exit the match early.
     Quit,
 }
 
@@ -33,10 +72,33 @@ pub enum Step {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum CurrentState {
     // You or another player have just joined.
-    // Player count, your index and your resume token are stored in history struct.
-    // TODO: transmit e.g. player names.
+    // Player count, player names, your index and your resume token are stored
+    // in history struct.
     PlayerJoined,
 
+    // Another player's connection has dropped, and the table is waiting for
+    // them to resume their seat. The disconnected players are stored in the
+    // lobby history struct.
+    PlayerDisconnected,
+
+    // A disconnected player has resumed their seat.
+    PlayerReconnected,
+
+    // A player has left, changed seats or said whether they are ready. The
+    // players are stored in the lobby history struct.
+    LobbyUpdated,
+
+    // You are now spectating. The public view of the match is stored in the
+    // history struct, and is sent again after every change.
+    Spectating,
+
+    // A player has sent a chat message or reaction. The recent messages are
+    // stored in the history struct.
+    ChatReceived,
+
+    // You have entered a room. The room code is stored in the history struct.
+    RoomJoined,
+
     // You have been rejected (e.g. because a game is ongoing). The reason is
     // stored in the lobby history struct.
     Excluded,
@@ -56,6 +118,10 @@ pub enum CurrentState {
     // Another player has bid. Their bid is stored in history struct.
     PlayerBid,
 
+    // Every player has passed without bidding. Whether the hand will be redealt
+    // or played without a contract is stored in the bidding history struct.
+    AllPassed,
+
     // A player (possibly you) has won the bid.
     // Winning player stored in history struct.
     BidWon,
@@ -67,14 +133,19 @@ pub enum CurrentState {
     // When they must choose how to use the kitty.
     WaitingForTheirKitty,
 
-    // When you must announce the suit of your joker.
-    WaitingForYourJokerSuit,
+    // When you must name a card to call your partner (i.e. you have won the
+    // bid in five-handed play).
+    WaitingForYourPartnerCall,
+
+    // When they must name a card to call their partner.
+    WaitingForTheirPartnerCall,
 
-    // When they must announce the suit of their joker.
-    WaitingForTheirJokerSuit,
+    // When they name a card to call their partner.
+    // Card is stored in the bidding won history struct.
+    PartnerCalled,
 
-    // When they announce the suit of their joker.
-    // types::Suit stored in history struct.
+    // When the joker is played without trumps, which nominates its suit.
+    // types::Suit stored in the plays history struct.
     JokerSuitAnnounced,
 
     // Waiting for you to choose a card to play. Your options are stored in the
@@ -90,7 +161,7 @@ pub enum CurrentState {
     TrickWon,
 
     // Your or the other team have won the game. The index of the winning team
-    // is stored in the match history struct.
+    // is stored in the latest game result in the match history struct.
     GameWon,
 
     // The new scores have been included in the history struct.
@@ -117,20 +188,73 @@ pub struct LobbyHistory {
     // Number of players currently joined.
     pub player_count: usize,
 
-    // Your index in the player list.
+    // The display names of the joined players. Ordered from player 1 to
+    // player N.
+    pub player_names: Vec<String>,
+
+    // Your index in the player list. Until the match starts, this is the order
+    // in which you joined. Players are then seated so that teams alternate.
     pub your_player_index: usize,
 
-    // Your index in the team list (i.e. in [0, 1]).
+    // Your index in the team list (e.g. in [0, 1] for four players). Without fixed teams, this is
+    // the same as your player index.
     pub your_team_index: usize,
+
+    // Whether each joined player is ready for the match to start. Ordered
+    // from player 1 to player N.
+    pub players_ready: Vec<bool>,
+
+    // Your secret token for resuming your seat if your connection drops. Don't
+    // share it with other players.
+    pub resume_token: String,
+
+    // The players whose connections have dropped and who haven't yet resumed
+    // their seats.
+    pub disconnected_player_indices: Vec<usize>,
+
+    // The players who have asked to swap seats with you. Ask to swap seats
+    // with one of them to agree.
+    pub swap_requests: Vec<usize>,
+}
+
+// The outcome of a completed game.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameResult {
+    // The winning bidder, if any player bid.
+    pub winning_bidder_index: Option<usize>,
+
+    // A Pass if every player passed and the hand was played without a contract.
+    pub winning_bid: types::Bid,
+
+    // The winning bidder's called partner, if they had one (i.e. in five-handed
+    // play, unless the bidder held the called card).
+    pub partner_index: Option<usize>,
+
+    // The number of tricks won by each team. Without fixed partnerships, each
+    // player is their own team (so this and the scores below are per player).
+    pub tricks_won: Vec<usize>,
+
+    // The index of the team that won the game. This is the bidding team if
+    // they made their contract, and the defending team otherwise (if there is
+    // only one). Without a contract, it is the team that won the most tricks
+    // (if there is one).
+    pub winning_team_index: Option<usize>,
+
+    // Whether the bidding team scored a slam bonus for winning every trick.
+    pub slam: bool,
+
+    // The change in each team's score from this game.
+    pub score_deltas: Vec<isize>,
+
+    // Each team's total score after this game.
+    pub score_totals: Vec<isize>,
 }
 
 // Background information about the match.
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct MatchHistory {
-    // Game history. TODO make more sophisticated.
-    //   (team 1 score delta, team 1 score total,
-    //    team 2 score delta, team 2 score total).
-    pub past_games: Vec<(isize, isize, isize, isize)>,
+    // The outcomes of completed games, from oldest to newest.
+    pub past_games: Vec<GameResult>,
 
     // The index of the team that won the entire match, if the match is over.
     pub winning_team_index: Option<usize>,
@@ -145,10 +269,13 @@ pub struct BiddingHistory {
     // Possible bids you can make, if it is your turn to bid.
     pub bid_options: Option<Vec<types::Bid>>,
 
-    // The last bids made by each player. Ordered from player 1 to player 4.
-    pub bids: Vec<Option<types::Bid>>, // Invariant: length of 4.
+    // The last bids made by each player. Ordered from player 1 to player N.
+    pub bids: Vec<Option<types::Bid>>, // Invariant: one per player.
 
     pub current_bidder_index: usize,
+
+    // How the hand continues, if every player has passed.
+    pub all_passed: Option<types::AllPassRule>,
 }
 
 // Background information about the bid that won.
@@ -158,38 +285,63 @@ pub struct WinningBidHistory {
 
     pub winning_bid: types::Bid, // Invariant: not a Pass.
 
+    // The last bids made by each player in the auction. Ordered from player 1
+    // to player N.
+    pub bids: Vec<Option<types::Bid>>, // Invariant: one per player.
+
     // The cards in your kitty, if you won the bidding.
     pub kitty: Option<Vec<types::Card>>,
 
     // The cards you discarded, if you won the bidding and have chosen which
     // cards to discard.
     pub discarded: Option<Vec<types::Card>>,
+
+    // The card named by the winning bidder to call their partner, once it has
+    // been named. Only used in five-handed play.
+    pub called_card: Option<types::Card>,
+}
+
+// A completed trick.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayedTrick {
+    // Index in the player list of the player who led the trick.
+    pub leader_index: usize,
+
+    // The cards played. Listed in order from player 1 to player N, with None
+    // for a player sitting out.
+    pub plays: Vec<Option<types::Play>>,
+
+    // Index in the player list of the player who won the trick.
+    pub winner_index: usize,
 }
 
 // Background information about the tricks being played.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PlaysHistory {
-    // The joker suit, if fixed.
+    // The suit nominated for the joker, once it has been played without trumps.
     pub joker_suit: Option<types::Suit>,
 
-    // The number of tricks you have won.
+    // The number of tricks your team has won.
     pub your_tricks_count: usize,
 
-    // The number of tricks they have won.
+    // The number of tricks the other teams have won.
     pub their_tricks_count: usize,
 
-    // The number of cards in each player's hand.
-    pub hand_sizes: Vec<usize>, // Invariant: length of 4.
+    // The number of cards in each player's hand. Zero for a player sitting out.
+    pub hand_sizes: Vec<usize>, // Invariant: one per player.
 
     // The previous trick, if there was one. Listed in order from player 1 to
-    // player 4. Inner Option is to support e.g. mis bids, where one player
+    // player N. Inner Option is to support e.g. mis bids, where one player
     // doesn't play.
     pub previous_trick: Option<Vec<Option<types::Play>>>,
 
     // The index of the player who won the last trick, if there is one.
     pub previous_trick_winner: Option<usize>,
 
-    // The ongoing trick. Listed in order from player 1 to player 4.
+    // Every trick completed so far in the hand, from first to last.
+    pub played_tricks: Vec<PlayedTrick>,
+
+    // The ongoing trick. Listed in order from player 1 to player N.
     pub current_trick: Vec<Option<types::Play>>,
 
     // Index in the player list of the currently-playing player.
@@ -197,6 +349,18 @@ pub struct PlaysHistory {
 
     // Your possible plays, if it is your turn to play a card.
     pub play_options: Option<Vec<types::Play>>,
+
+    // Index in the player list of the player sitting out of the hand, if there
+    // is one (i.e. the partner of a misere bidder).
+    pub inactive_player_index: Option<usize>,
+
+    // The remaining hand of an open misere bidder, once the opening lead has
+    // been made. Not populated for the bidder themselves.
+    pub open_hand: Option<Vec<types::Card>>,
+
+    // Index in the player list of the winning bidder's called partner, once
+    // the called card has been played. Only used in five-handed play.
+    pub partner_index: Option<usize>,
 }
 
 // Background information about the current game (i.e. the current bidding,
@@ -211,12 +375,38 @@ pub struct GameHistory {
     pub winning_bid_history: Option<WinningBidHistory>,
 
     pub plays_history: Option<PlaysHistory>,
+
+    // When the current turn runs out, in milliseconds since the Unix epoch, if
+    // it is timed. A default action is then taken for the player.
+    pub turn_deadline: Option<u64>,
+}
+
+// What a player has sent to the table.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ChatContent {
+    Text(String),
+    Reaction(types::Reaction),
+}
+
+// A chat message or reaction sent by a player.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatMessage {
+    // The sender's index in the player list when they sent it.
+    pub player_index: usize,
+
+    // The sender's display name.
+    pub name: String,
+
+    pub content: ChatContent,
 }
 
 // Background information about the session. Sub-structs are populated as they
 // become valid.
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct History {
+    // The code of the room you have just entered, if you have.
+    pub room_code: Option<String>,
+
     pub lobby_history: Option<LobbyHistory>,
 
     pub match_history: Option<MatchHistory>,
@@ -228,6 +418,9 @@ pub struct History {
 
     // Some other error, if there is one.
     pub error: Option<String>,
+
+    // The most recent chat messages and reactions, from oldest to newest.
+    pub recent_chat: Vec<ChatMessage>,
 }
 
 // Top level state information sent to the client.
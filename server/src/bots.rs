@@ -0,0 +1,306 @@
+// Computer players that fill empty seats. A bot is seated like any other player, and acts by reading
+// its own history whenever the table is waiting for it. Basic bots play by simple rules of thumb:
+// they bid what their hand looks good for, discard their weakest cards, follow suit low and win
+// tricks as cheaply as they can. Bots on harder difficulties instead search for their bids, discards
+// and plays (see search.rs), though they still call partners by rules of thumb.
+
+use crate::api;
+use crate::scoring;
+use crate::search;
+use crate::tricks;
+use crate::types::*;
+
+// Returns the step the bot in the given seat takes in the given state, or None if it has nothing
+// to do.
+pub fn choose_step(
+    history: &api::History,
+    state: &api::CurrentState,
+    player_index: usize,
+    rules: Rules,
+    difficulty: BotDifficulty,
+) -> Option<api::Step> {
+    // Searching bots simulate this many deals for each decision.
+    let simulations = match difficulty {
+        BotDifficulty::Basic => None,
+        BotDifficulty::Easy => Some(100),
+        BotDifficulty::Medium => Some(400),
+        BotDifficulty::Hard => Some(1600),
+    };
+
+    match state {
+        // Bots agree to any seat swap, and are always ready for the match to start.
+        api::CurrentState::PlayerJoined => {
+            let lobby_history = history.lobby_history.as_ref()?;
+            if let Some(&other_index) = lobby_history.swap_requests.first() {
+                return Some(api::Step::SwapSeats(other_index));
+            }
+            (!lobby_history.players_ready[player_index]).then_some(api::Step::Ready(true))
+        }
+
+        api::CurrentState::WaitingForYourBid if simulations.is_some() => {
+            let game_history = history.game_history.as_ref()?;
+            search::choose_bid(game_history, player_index, rules, simulations?)
+                .map(api::Step::MakeBid)
+        }
+
+        api::CurrentState::WaitingForYourKitty if simulations.is_some() => {
+            let game_history = history.game_history.as_ref()?;
+            search::choose_discard(game_history, player_index, rules, simulations?)
+                .map(api::Step::DiscardCards)
+        }
+
+        api::CurrentState::WaitingForYourPlay if simulations.is_some() => {
+            let game_history = history.game_history.as_ref()?;
+            search::choose_play(game_history, player_index, rules, simulations?)
+                .map(api::Step::MakePlay)
+        }
+
+        api::CurrentState::WaitingForYourBid => {
+            let game_history = history.game_history.as_ref()?;
+            let bid_options = game_history
+                .bidding_history
+                .as_ref()?
+                .bid_options
+                .as_ref()?;
+            Some(api::Step::MakeBid(choose_bid(
+                bid_options,
+                &game_history.hand,
+            )))
+        }
+
+        api::CurrentState::WaitingForYourKitty => {
+            let game_history = history.game_history.as_ref()?;
+            let winning_bid_history = game_history.winning_bid_history.as_ref()?;
+            let held_cards = game_history
+                .hand
+                .iter()
+                .chain(winning_bid_history.kitty.as_ref()?)
+                .copied()
+                .collect::<Vec<_>>();
+
+            Some(api::Step::DiscardCards(weakest_cards(
+                tricks::bid_trumps(winning_bid_history.winning_bid),
+                &held_cards,
+                3,
+            )))
+        }
+
+        api::CurrentState::WaitingForYourPartnerCall => {
+            let game_history = history.game_history.as_ref()?;
+            let winning_bid_history = game_history.winning_bid_history.as_ref()?;
+            Some(api::Step::CallPartner(strongest_missing_card(
+                tricks::bid_trumps(winning_bid_history.winning_bid),
+                &game_history.hand,
+                rules,
+            )))
+        }
+
+        api::CurrentState::WaitingForYourPlay => {
+            choose_play(history.game_history.as_ref()?, player_index, rules)
+                .map(api::Step::MakePlay)
+        }
+
+        _ => None,
+    }
+}
+
+// Returns the given number of weakest cards, where trumps are stronger than every other card.
+pub fn weakest_cards(trumps: BidSuit, cards: &[Card], count: usize) -> Vec<Card> {
+    let mut cards = cards.to_vec();
+    cards.sort_by_key(|&card| tricks::play_strength(trumps, tricks::card_play(trumps, card)));
+    cards.truncate(count);
+
+    cards
+}
+
+// Returns the strongest card in the deck that isn't in the given hand. Calling it as a partner
+// gives the bidder the best support.
+pub fn strongest_missing_card(trumps: BidSuit, hand: &[Card], rules: Rules) -> Card {
+    rules
+        .deck_cards()
+        .into_iter()
+        .filter(|card| !hand.contains(card))
+        .max_by_key(|&card| tricks::play_strength(trumps, tricks::card_play(trumps, card)))
+        .unwrap()
+}
+
+// Returns the suit the given hand holds the most cards of.
+fn longest_suit(hand: &[Card]) -> Suit {
+    [Suit::Spades, Suit::Clubs, Suit::Diamonds, Suit::Hearts]
+        .into_iter()
+        .max_by_key(|&suit| {
+            hand.iter()
+                .filter(|card| matches!(card, Card::SuitedCard(c) if c.suit == suit))
+                .count()
+        })
+        .unwrap()
+}
+
+// Returns the cheapest of the given bids that the hand is expected to make, or a pass if the hand
+// isn't good enough for any of them. Bots never bid misere.
+fn choose_bid(bid_options: &[Bid], hand: &[Card]) -> Bid {
+    bid_options
+        .iter()
+        .copied()
+        .filter(
+            |bid| matches!(bid, Bid::Tricks(count, suit) if *count <= expected_tricks(*suit, hand)),
+        )
+        .min_by_key(|&bid| scoring::bid_value(bid))
+        .unwrap_or(Bid::Pass)
+}
+
+// Returns a rough count of the tricks the given hand would win with the given trumps. Top trumps,
+// aces and the joker count as a trick each, and kings and low trumps as half a trick. The kitty is
+// expected to add another trick.
+fn expected_tricks(trumps: BidSuit, hand: &[Card]) -> usize {
+    const KING: usize = ACE - 1;
+
+    let half_tricks = hand
+        .iter()
+        .map(|&card| {
+            let play = tricks::card_play(trumps, card);
+            match (play, card) {
+                (_, Card::Joker) => 2,
+                (Play::SuitedCard(c), _) if is_trump(trumps, play) => {
+                    if c.face == JACK || c.face >= KING {
+                        2
+                    } else {
+                        1
+                    }
+                }
+                (Play::SuitedCard(c), _) if c.face == ACE => 2,
+                (Play::SuitedCard(c), _) if c.face == KING => 1,
+                _ => 0,
+            }
+        })
+        .sum::<usize>();
+
+    (half_tricks + 2) / 2
+}
+
+// Returns the play to make from the player's options, by rules of thumb.
+fn choose_play(game_history: &api::GameHistory, player_index: usize, rules: Rules) -> Option<Play> {
+    let plays_history = game_history.plays_history.as_ref()?;
+    let play_options = plays_history.play_options.as_ref()?;
+    let winning_bid_history = game_history.winning_bid_history.as_ref();
+
+    // There is no winning bid if the hand is played after every player passed.
+    let trumps = winning_bid_history
+        .map(|h| tricks::bid_trumps(h.winning_bid))
+        .unwrap_or(BidSuit::NoTrumps);
+    let misere_bidder_index = winning_bid_history
+        .filter(|h| matches!(h.winning_bid, Bid::Mis | Bid::OpenMis))
+        .map(|h| h.winning_bidder_index);
+
+    let trick = &plays_history.current_trick;
+    let leader_index = trick_leader(trick, player_index, plays_history.inactive_player_index);
+
+    let play = rule_of_thumb_play(
+        trumps,
+        play_options,
+        trick,
+        leader_index,
+        misere_bidder_index,
+        player_index,
+        |a, b| same_side(game_history, rules, a, b),
+    );
+
+    // A joker led without trumps is nominated as the suit the bot holds most of.
+    Some(match play {
+        Play::Joker(_) if trumps == BidSuit::NoTrumps && leader_index.is_none() => {
+            Play::Joker(longest_suit(&game_history.hand))
+        }
+        play => play,
+    })
+}
+
+// Returns the play to make from the given options. The player leads their strongest card, and when
+// following they win the trick as cheaply as they can unless their side is already winning. A
+// misere bidder ducks under the winning card instead, and their opponents play low.
+pub fn rule_of_thumb_play(
+    trumps: BidSuit,
+    play_options: &[Play],
+    trick: &[Option<Play>],
+    leader_index: Option<usize>,
+    misere_bidder_index: Option<usize>,
+    player_index: usize,
+    same_side: impl Fn(usize, usize) -> bool,
+) -> Play {
+    let strength = |play: &Play| tricks::play_strength(trumps, *play);
+
+    // Invariant: a player always has a play to make.
+    let lowest = play_options.iter().copied().min_by_key(strength).unwrap();
+
+    let Some(led_play) = leader_index.and_then(|i| trick[i]) else {
+        return if misere_bidder_index == Some(player_index) {
+            lowest
+        } else {
+            play_options.iter().copied().max_by_key(strength).unwrap()
+        };
+    };
+
+    let led_suit = tricks::effective_suit(trumps, led_play);
+    let rank = |play: &Play| tricks::play_rank(trumps, led_suit, *play);
+    let winner_index = tricks::trick_winner(trumps, led_suit, trick);
+    let winning_rank = trick[winner_index].and_then(|play| rank(&play));
+
+    let play = match misere_bidder_index {
+        // Throw the highest card that still loses the trick.
+        Some(bidder_index) if bidder_index == player_index => play_options
+            .iter()
+            .copied()
+            .filter(|play| rank(play) < winning_rank)
+            .max_by_key(|play| (rank(play), strength(play))),
+        Some(_) => None,
+        None if same_side(player_index, winner_index) => None,
+        None => play_options
+            .iter()
+            .copied()
+            .filter(|play| rank(play) > winning_rank)
+            .min_by_key(rank),
+    };
+
+    play.unwrap_or(lowest)
+}
+
+// Returns the player who led the ongoing trick, if it has been led. The leader is the earliest of
+// the players who have played, counting back from the given player.
+pub fn trick_leader(
+    trick: &[Option<Play>],
+    player_index: usize,
+    inactive_player_index: Option<usize>,
+) -> Option<usize> {
+    let player_count = trick.len();
+    (1..player_count)
+        .map(|k| (player_index + player_count - k) % player_count)
+        .filter(|&j| Some(j) != inactive_player_index)
+        .take_while(|&j| trick[j].is_some())
+        .last()
+}
+
+// Returns whether the given players are on the same side, as far as the first player knows. A
+// called partner sides with the bidder once they are known.
+fn same_side(game_history: &api::GameHistory, rules: Rules, a: usize, b: usize) -> bool {
+    if rules.team_index(a) == rules.team_index(b) {
+        return true;
+    }
+
+    let bidder_index = game_history
+        .winning_bid_history
+        .as_ref()
+        .map(|h| h.winning_bidder_index);
+    let partner_index = game_history
+        .plays_history
+        .as_ref()
+        .and_then(|h| h.partner_index);
+
+    matches!(
+        (bidder_index, partner_index),
+        (Some(i), Some(j)) if (i, j) == (a, b) || (j, i) == (a, b)
+    )
+}
+
+// Returns whether the given play is a trump.
+fn is_trump(trumps: BidSuit, play: Play) -> bool {
+    matches!(trumps, BidSuit::Suit(suit) if tricks::effective_suit(trumps, play) == suit)
+}
@@ -2,8 +2,9 @@
 // them conceptually from the "web bridge" that ferries them from web clients.
 
 use crate::api;
+use crate::types;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use log::error;
 use tokio::sync::mpsc;
@@ -31,15 +32,28 @@ pub type ClientEventReceiver = mpsc::UnboundedReceiver<ClientEvent>;
 // An async transmitter used to send events to a client.
 pub type EngineEventSender = mpsc::UnboundedSender<api::State>;
 
+// An async transmitter used by a session to tell the room manager that the room with the given code
+// has emptied, and can be closed.
+pub type RoomEmptySender = mpsc::UnboundedSender<String>;
+
 // Used to transmit engine events to a set of clients.
 pub struct ClientMap {
     client_txs: HashMap<ClientId, EngineEventSender>,
+
+    // The clients who are following the match without playing in it.
+    spectators: HashSet<ClientId>,
+
+    // The computer players and their difficulties. They have no connection, and act by reading
+    // their own histories.
+    bots: HashMap<ClientId, types::BotDifficulty>,
 }
 
 impl ClientMap {
     pub fn new() -> Self {
         Self {
             client_txs: HashMap::new(),
+            spectators: HashSet::new(),
+            bots: HashMap::new(),
         }
     }
 
@@ -49,9 +63,40 @@ impl ClientMap {
 
     pub fn remove_client(&mut self, id: &ClientId) {
         self.client_txs.remove(id);
+        self.spectators.remove(id);
+        self.bots.remove(id);
+    }
+
+    pub fn add_bot(&mut self, id: &ClientId, difficulty: types::BotDifficulty) {
+        self.bots.insert(id.clone(), difficulty);
+    }
+
+    pub fn bot_difficulty(&self, id: &ClientId) -> Option<types::BotDifficulty> {
+        self.bots.get(id).copied()
+    }
+
+    pub fn add_spectator(&mut self, id: &ClientId) {
+        self.spectators.insert(id.clone());
+    }
+
+    pub fn remove_spectator(&mut self, id: &ClientId) {
+        self.spectators.remove(id);
+    }
+
+    // Returns whether no client is connected. Bots don't count, since they have no connection.
+    pub fn is_empty(&self) -> bool {
+        self.client_txs.is_empty()
+    }
+
+    pub fn sender(&self, id: &ClientId) -> Option<EngineEventSender> {
+        self.client_txs.get(id).cloned()
     }
 
     pub fn send_event(&self, id: &ClientId, history: api::History, state: api::CurrentState) {
+        if self.bots.contains_key(id) {
+            return;
+        }
+
         let Some(tx) = self.client_txs.get(id) else {
             error!("Attempted to send message to unregistered [client {}].", id);
             return;
@@ -61,4 +106,82 @@ impl ClientMap {
             error!("Engine couldn't send event to [client {}].", id);
         }
     }
+
+    // Sends the public view of the players' histories to every spectator. The state can be any
+    // player's, since spectators are never the ones acting.
+    pub fn send_spectators(&self, players: &[(ClientId, api::History)], state: api::CurrentState) {
+        if self.spectators.is_empty() {
+            return;
+        }
+
+        let history = public_history(players);
+        let state = match state {
+            api::CurrentState::WaitingForYourBid => api::CurrentState::WaitingForTheirBid,
+            api::CurrentState::WaitingForYourKitty => api::CurrentState::WaitingForTheirKitty,
+            api::CurrentState::WaitingForYourPartnerCall => {
+                api::CurrentState::WaitingForTheirPartnerCall
+            }
+            api::CurrentState::WaitingForYourPlay => api::CurrentState::WaitingForTheirPlay,
+            state => state,
+        };
+
+        for id in &self.spectators {
+            self.send_event(id, history.clone(), state.clone());
+        }
+    }
+}
+
+// Returns what every player can see of the match: the bids, tricks, scores and any open misere
+// hand, but no player's private cards. Built from the first player's history, so per-team counts
+// are from the first team's point of view.
+pub fn public_history(players: &[(ClientId, api::History)]) -> api::History {
+    let Some((_, history)) = players.first() else {
+        return api::History::default();
+    };
+
+    let plays_histories = players
+        .iter()
+        .filter_map(|(_, history)| history.game_history.as_ref()?.plays_history.as_ref())
+        .collect::<Vec<_>>();
+
+    // The open misere bidder's hand is shown to everyone except the bidder, who might be the
+    // first player.
+    let open_hand = plays_histories.iter().find_map(|h| h.open_hand.clone());
+
+    // A called partner knows who they are before everyone else does, and might be the first
+    // player.
+    let partner_index = plays_histories
+        .iter()
+        .map(|h| h.partner_index)
+        .reduce(|a, b| a.and(b))
+        .flatten();
+
+    api::History {
+        lobby_history: history.lobby_history.clone().map(|h| api::LobbyHistory {
+            resume_token: String::new(),
+            swap_requests: Vec::new(),
+            ..h
+        }),
+        game_history: history.game_history.clone().map(|h| api::GameHistory {
+            hand: Vec::new(),
+            bidding_history: h.bidding_history.map(|h| api::BiddingHistory {
+                bid_options: None,
+                ..h
+            }),
+            winning_bid_history: h.winning_bid_history.map(|h| api::WinningBidHistory {
+                kitty: None,
+                discarded: None,
+                ..h
+            }),
+            plays_history: h.plays_history.map(|h| api::PlaysHistory {
+                play_options: None,
+                open_hand,
+                partner_index,
+                ..h
+            }),
+            turn_deadline: h.turn_deadline,
+        }),
+        error: None,
+        ..history.clone()
+    }
 }
@@ -1,12 +1,23 @@
 // When given a valid command, always responds with a dummy state.
 // Try: wscat -c 127.0.0.1:8080 -x '"Poll"'
+//
+// Usage: server [address] [rules.json] [deck seed | deck.json]
 
 use std::env;
+use std::fs;
+use std::process;
+
+use log::{error, info};
 
 mod api;
+mod bots;
 mod events;
+mod rooms;
+mod scoring;
+mod search;
 mod session;
 mod stages;
+mod tricks;
 mod types;
 mod web_bridge;
 
@@ -18,6 +29,42 @@ async fn main() {
         .nth(1)
         .unwrap_or_else(|| "127.0.0.1:8080".to_string());
 
+    // House rules are read from an optional JSON file, e.g. '{"slam_bonus": false}'.
+    let rules: types::Rules = env::args()
+        .nth(2)
+        .map(|path| {
+            let config = fs::read_to_string(&path).expect("Failed to read rules file");
+            serde_json::from_str(&config).expect("Failed to parse rules file")
+        })
+        .unwrap_or_default();
+    if let Err(err) = rules.validate() {
+        error!("Invalid rules file: {}", err);
+        process::exit(1);
+    }
+    info!("Playing with rules: {:?}", rules);
+
+    // Deals are shuffled from a seed, which is logged so that a match can be replayed. Instead of a
+    // seed, a JSON file listing the cards in order can be given to replay a single deal. Either is
+    // used by the first room created for as many players as these rules; every other room is
+    // shuffled from its own random seed.
+    let deck = env::args().nth(3).map(|arg| match arg.parse::<u64>() {
+        Ok(seed) => {
+            info!("Dealing with seed: {}", seed);
+            types::Deck::from_seed(seed, rules)
+        }
+        Err(_) => {
+            info!("Dealing from fixed deck: {}", arg);
+            let config = fs::read_to_string(&arg).expect("Failed to read deck file");
+            let cards = serde_json::from_str(&config).expect("Failed to parse deck file");
+            types::Deck::fixed(cards, rules.player_count).unwrap_or_else(|err| {
+                error!("Invalid deck file: {}", err);
+                process::exit(1);
+            })
+        }
+    });
+
     let rx = web_bridge::connect_bridge(addr);
-    session::Session::new(rx).run_main_loop().await;
+    rooms::RoomManager::new(rx, rules, deck)
+        .run_main_loop()
+        .await;
 }
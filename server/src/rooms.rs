@@ -0,0 +1,306 @@
+// Sits between the web bridge and the sessions, so that one server can host many tables at once.
+// Clients first create a room or join an existing room by its code. From then on, their events are
+// forwarded to that room's session, which runs in its own task until every client has left and no
+// seat is being held for a disconnected player.
+
+use crate::api;
+use crate::events;
+use crate::events::ClientEventPayload::Connect;
+use crate::events::ClientEventPayload::Disconnect;
+use crate::events::ClientEventPayload::Step;
+use crate::session;
+use crate::types;
+
+use std::collections::HashMap;
+
+use log::{error, info};
+use rand::Rng;
+use tokio::sync::mpsc;
+
+// The number of letters in a room code.
+const ROOM_CODE_LENGTH: usize = 4;
+
+// A running session and the number of clients inside it.
+struct Room {
+    event_tx: mpsc::UnboundedSender<events::ClientEvent>,
+    client_count: usize,
+}
+
+pub struct RoomManager {
+    event_rx: events::ClientEventReceiver,
+
+    // Reply channels for all connected clients, whether or not they are in a room.
+    clients: events::ClientMap,
+
+    // The house rules a room is played under, unless its creator chooses others.
+    rules: types::Rules,
+
+    // The deck given at startup to replay a match, if there was one. It is dealt to the first room
+    // created for as many players as the rules above. Every other room shuffles from its own
+    // random seed.
+    replay_deck: Option<types::Deck>,
+
+    // Open rooms, keyed by their codes.
+    rooms: HashMap<String, Room>,
+
+    // Used by sessions to report that their rooms have emptied.
+    room_empty_tx: events::RoomEmptySender,
+    room_empty_rx: mpsc::UnboundedReceiver<String>,
+
+    // The code of the room each client is in, if they are in one.
+    client_rooms: HashMap<events::ClientId, String>,
+}
+
+impl RoomManager {
+    pub fn new(
+        event_rx: events::ClientEventReceiver,
+        rules: types::Rules,
+        replay_deck: Option<types::Deck>,
+    ) -> Self {
+        let (room_empty_tx, room_empty_rx) = mpsc::unbounded_channel();
+        Self {
+            event_rx,
+            clients: events::ClientMap::new(),
+            rules,
+            replay_deck,
+            rooms: HashMap::new(),
+            room_empty_tx,
+            room_empty_rx,
+            client_rooms: HashMap::new(),
+        }
+    }
+
+    pub async fn run_main_loop(&mut self) {
+        loop {
+            // Wait for the next event, or for a session to report that its room has emptied. The
+            // manager holds a sender, so the reports never end.
+            let event = tokio::select! {
+                event = self.event_rx.recv() => event,
+                Some(code) = self.room_empty_rx.recv() => {
+                    self.close_room(&code);
+                    continue;
+                }
+            };
+
+            let Some(event) = event else {
+                info!("Bridge dropped - exiting.");
+                return;
+            };
+
+            match &event.payload {
+                // New response channel received. It is passed on once the client enters a room.
+                Connect(tx) => {
+                    self.clients.add_client(&event.id, tx.clone());
+                    info!("New [client {}] connected to room manager.", event.id);
+                }
+
+                // Channel to client dropped.
+                Disconnect => {
+                    self.clients.remove_client(&event.id);
+                    self.leave_room(event);
+                }
+
+                Step(api::Step::CreateRoom(rules)) => {
+                    if self.reject_room_member(&event.id) {
+                        continue;
+                    }
+
+                    let rules = rules.unwrap_or(self.rules);
+                    if let Err(err) = rules.validate() {
+                        error!(
+                            "[client {}] tried to create a room with invalid rules",
+                            event.id
+                        );
+                        self.clients.send_event(
+                            &event.id,
+                            api::History {
+                                error: Some(err),
+                                ..Default::default()
+                            },
+                            api::CurrentState::Error,
+                        );
+                        continue;
+                    }
+
+                    let code = self.new_room_code();
+                    let (event_tx, event_rx) = mpsc::unbounded_channel();
+                    let player_count = self.rules.player_count;
+                    let deck = match self
+                        .replay_deck
+                        .take_if(|_| rules.player_count == player_count)
+                    {
+                        Some(deck) => {
+                            info!("Dealing room {} from the replay deck.", code);
+                            deck
+                        }
+                        None => {
+                            let seed = rand::random();
+                            info!("Dealing room {} with seed: {}", code, seed);
+                            types::Deck::from_seed(seed, rules)
+                        }
+                    };
+
+                    let mut session = session::Session::new(
+                        event_rx,
+                        code.clone(),
+                        self.room_empty_tx.clone(),
+                        rules,
+                        deck,
+                    );
+                    tokio::spawn(async move { session.run_main_loop().await });
+
+                    info!(
+                        "[client {}] created room {} with rules: {:?}",
+                        event.id, code, rules
+                    );
+                    self.rooms.insert(
+                        code.clone(),
+                        Room {
+                            event_tx,
+                            client_count: 0,
+                        },
+                    );
+                    self.enter_room(&event.id, code);
+                }
+
+                Step(api::Step::JoinRoom(code)) => {
+                    if self.reject_room_member(&event.id) {
+                        continue;
+                    }
+
+                    let code = code.to_uppercase();
+                    if !self.rooms.contains_key(&code) {
+                        error!("[client {}] tried to join missing room {}", event.id, code);
+                        self.clients.send_event(
+                            &event.id,
+                            api::History {
+                                error: Some("No room has that code.".to_string()),
+                                ..Default::default()
+                            },
+                            api::CurrentState::Error,
+                        );
+                        continue;
+                    }
+
+                    info!("[client {}] joined room {}.", event.id, code);
+                    self.enter_room(&event.id, code);
+                }
+
+                // Any other step is handled by the client's room.
+                Step(_) => {
+                    let Some(room) = self
+                        .client_rooms
+                        .get(&event.id)
+                        .and_then(|code| self.rooms.get(code))
+                    else {
+                        error!("[client {}] sent a step outside a room", event.id);
+                        self.clients.send_event(
+                            &event.id,
+                            api::History {
+                                error: Some("Create or join a room first.".to_string()),
+                                ..Default::default()
+                            },
+                            api::CurrentState::Error,
+                        );
+                        continue;
+                    };
+
+                    if room.event_tx.send(event).is_err() {
+                        error!("Room manager couldn't forward a step to a closed room.");
+                    }
+                }
+            }
+        }
+    }
+
+    // Sends an error response to a client who is already in a room. Returns whether the client was
+    // rejected.
+    fn reject_room_member(&self, id: &events::ClientId) -> bool {
+        if !self.client_rooms.contains_key(id) {
+            return false;
+        }
+
+        error!("[client {}] tried to enter a second room", id);
+        self.clients.send_event(
+            id,
+            api::History {
+                error: Some("Already in a room.".to_string()),
+                ..Default::default()
+            },
+            api::CurrentState::Error,
+        );
+
+        true
+    }
+
+    // Adds the client to the given room, hands the room their reply channel and notifies them of
+    // the room's code.
+    fn enter_room(&mut self, id: &events::ClientId, code: String) {
+        // Invariant: the room exists and the client is connected.
+        let room = self.rooms.get_mut(&code).unwrap();
+        let tx = self.clients.sender(id).unwrap();
+        if room
+            .event_tx
+            .send(events::ClientEvent {
+                id: id.clone(),
+                payload: Connect(tx),
+            })
+            .is_err()
+        {
+            error!("Room manager couldn't connect [client {}] to room.", id);
+        }
+        room.client_count += 1;
+
+        self.clients.send_event(
+            id,
+            api::History {
+                room_code: Some(code.clone()),
+                ..Default::default()
+            },
+            api::CurrentState::RoomJoined,
+        );
+        self.client_rooms.insert(id.clone(), code);
+    }
+
+    // Forwards a disconnection to the client's room, if they are in one. The room stays open (e.g.
+    // holding the client's seat for them to resume) until its session reports it has emptied.
+    fn leave_room(&mut self, event: events::ClientEvent) {
+        let Some(code) = self.client_rooms.remove(&event.id) else {
+            return;
+        };
+
+        // Invariant: rooms stay open while they have clients.
+        let room = self.rooms.get_mut(&code).unwrap();
+        if room.event_tx.send(event).is_err() {
+            error!("Room manager couldn't forward a disconnection to a closed room.");
+        }
+
+        room.client_count -= 1;
+    }
+
+    // Closes the given room, which ends its session, unless a client has entered it since its
+    // session reported it had emptied.
+    fn close_room(&mut self, code: &str) {
+        if self
+            .rooms
+            .get(code)
+            .is_some_and(|room| room.client_count == 0)
+        {
+            info!("Room {} is empty - closing.", code);
+            self.rooms.remove(code);
+        }
+    }
+
+    // Returns a random code that isn't used by any open room.
+    fn new_room_code(&self) -> String {
+        loop {
+            let code = (0..ROOM_CODE_LENGTH)
+                .map(|_| rand::thread_rng().gen_range('A'..='Z'))
+                .collect::<String>();
+
+            if !self.rooms.contains_key(&code) {
+                return code;
+            }
+        }
+    }
+}
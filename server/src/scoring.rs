@@ -0,0 +1,283 @@
+// Scoring of completed games, following the Avondale schedule. Trick bids are worth 40 points for
+// six spades, increasing by 20 points per suit (in the order spades, clubs, diamonds, hearts, no
+// trumps) and by 100 points per extra trick. Misere is worth 250 points and open misere 500.
+//
+// Optionally, a team that bids fewer than 250 points and wins every trick scores a slam bonus of
+// 250 points instead of the value of their bid.
+
+use crate::types::*;
+
+// The points a defending team scores for each trick it wins.
+const DEFENDER_TRICK_POINTS: isize = 10;
+
+// The points scored for a slam, and the bid value beneath which a slam scores them.
+const SLAM_POINTS: isize = 250;
+
+// The total number of tricks in a hand.
+const HAND_TRICKS: usize = 10;
+
+// Returns the number of points the given bid is worth.
+pub fn bid_value(bid: Bid) -> isize {
+    match bid {
+        Bid::Tricks(count, suit) => {
+            let suit_value = match suit {
+                BidSuit::Suit(Suit::Spades) => 40,
+                BidSuit::Suit(Suit::Clubs) => 60,
+                BidSuit::Suit(Suit::Diamonds) => 80,
+                BidSuit::Suit(Suit::Hearts) => 100,
+                BidSuit::NoTrumps => 120,
+            };
+
+            suit_value + 100 * (count as isize - 6)
+        }
+        Bid::Mis => 250,
+        Bid::OpenMis => 500,
+        Bid::Pass => 0,
+    }
+}
+
+// Returns whether the bidder has made their contract, given their called partner (if any) and the
+// number of tricks won by each player.
+pub fn contract_made(
+    bid: Bid,
+    bidder_index: usize,
+    partner_index: Option<usize>,
+    player_teams: &[usize],
+    player_tricks: &[usize],
+) -> bool {
+    match bid {
+        // Misere bidders must lose every trick.
+        Bid::Mis | Bid::OpenMis => player_tricks[bidder_index] == 0,
+        Bid::Tricks(count, _) => {
+            bidding_tricks(bidder_index, partner_index, player_teams, player_tricks) >= count
+        }
+        Bid::Pass => unreachable!(),
+    }
+}
+
+// Returns whether the bidding side has won a slam (i.e. every trick on a bid worth fewer than 250
+// points), given the bidder's called partner (if any) and the number of tricks won by each player.
+pub fn slam_made(
+    bid: Bid,
+    bidder_index: usize,
+    partner_index: Option<usize>,
+    player_teams: &[usize],
+    player_tricks: &[usize],
+) -> bool {
+    matches!(bid, Bid::Tricks(_, _))
+        && bid_value(bid) < SLAM_POINTS
+        && bidding_tricks(bidder_index, partner_index, player_teams, player_tricks) == HAND_TRICKS
+}
+
+// Returns the change in score for each team, given the bidder's called partner (if any) and the
+// number of tricks won by each player. The bidding side gains the value of the bid if they make
+// their contract (or the slam bonus if it applies), and loses it otherwise. Defending teams score
+// for each trick they win, except against a misere.
+pub fn score_deltas(
+    bid: Bid,
+    bidder_index: usize,
+    partner_index: Option<usize>,
+    player_teams: &[usize],
+    player_tricks: &[usize],
+    team_count: usize,
+    slam_bonus: bool,
+) -> Vec<isize> {
+    let bidding_teams = bidding_teams(bidder_index, partner_index, player_teams);
+    let made = contract_made(
+        bid,
+        bidder_index,
+        partner_index,
+        player_teams,
+        player_tricks,
+    );
+    let slam = slam_bonus
+        && slam_made(
+            bid,
+            bidder_index,
+            partner_index,
+            player_teams,
+            player_tricks,
+        );
+
+    (0..team_count)
+        .map(|team| {
+            if bidding_teams.contains(&team) {
+                if slam {
+                    SLAM_POINTS
+                } else if made {
+                    bid_value(bid)
+                } else {
+                    -bid_value(bid)
+                }
+            } else if matches!(bid, Bid::Mis | Bid::OpenMis) {
+                0
+            } else {
+                DEFENDER_TRICK_POINTS * team_tricks(team, player_teams, player_tricks) as isize
+            }
+        })
+        .collect()
+}
+
+// Returns the teams on the bidding side: the bidder's team, and their called partner's team if it
+// differs.
+pub fn bidding_teams(
+    bidder_index: usize,
+    partner_index: Option<usize>,
+    player_teams: &[usize],
+) -> Vec<usize> {
+    let mut teams = vec![player_teams[bidder_index]];
+    if let Some(team) = partner_index.map(|p| player_teams[p]) {
+        if team != teams[0] {
+            teams.push(team);
+        }
+    }
+
+    teams
+}
+
+// Returns the number of tricks won by the bidding side.
+fn bidding_tricks(
+    bidder_index: usize,
+    partner_index: Option<usize>,
+    player_teams: &[usize],
+    player_tricks: &[usize],
+) -> usize {
+    bidding_teams(bidder_index, partner_index, player_teams)
+        .iter()
+        .map(|&team| team_tricks(team, player_teams, player_tricks))
+        .sum()
+}
+
+// Returns the change in score for each team in a hand played without a contract (i.e. after every
+// player has passed), where every team scores for each trick it wins.
+pub fn no_contract_score_deltas(
+    player_teams: &[usize],
+    player_tricks: &[usize],
+    team_count: usize,
+) -> Vec<isize> {
+    (0..team_count)
+        .map(|team| DEFENDER_TRICK_POINTS * team_tricks(team, player_teams, player_tricks) as isize)
+        .collect()
+}
+
+// Returns the number of tricks won by the given team.
+pub fn team_tricks(team_index: usize, player_teams: &[usize], player_tricks: &[usize]) -> usize {
+    player_teams
+        .iter()
+        .zip(player_tricks)
+        .filter(|(&t, _)| t == team_index)
+        .map(|(_, &n)| n)
+        .sum()
+}
+
+// Returns the index of the team that has won the match, if any, given each team's total score after
+// the latest game and the teams that made their contract in that game. A team can only win by
+// making its own contract, and wins by default if its opponents' score falls to the negative of
+// the target score.
+pub fn match_winner(
+    score_totals: &[isize],
+    contract_teams: &[usize],
+    target_score: isize,
+) -> Option<usize> {
+    if let Some(&team) = contract_teams
+        .iter()
+        .filter(|&&t| score_totals[t] >= target_score)
+        .max_by_key(|&&t| score_totals[t])
+    {
+        return Some(team);
+    }
+
+    if score_totals.iter().any(|&s| s <= -target_score) {
+        return (0..score_totals.len()).max_by_key(|&t| score_totals[t]);
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEAMS: [usize; 4] = [0, 1, 0, 1];
+
+    fn tricks(count: usize, suit: Suit) -> Bid {
+        Bid::Tricks(count, BidSuit::Suit(suit))
+    }
+
+    #[test]
+    fn bids_follow_the_avondale_table() {
+        assert_eq!(bid_value(tricks(6, Suit::Spades)), 40);
+        assert_eq!(bid_value(tricks(7, Suit::Diamonds)), 180);
+        assert_eq!(bid_value(tricks(10, Suit::Hearts)), 500);
+        assert_eq!(bid_value(Bid::Tricks(10, BidSuit::NoTrumps)), 520);
+        assert_eq!(bid_value(Bid::Mis), 250);
+        assert_eq!(bid_value(Bid::OpenMis), 500);
+    }
+
+    #[test]
+    fn made_and_set_contracts() {
+        // Seven hearts made, with the defenders taking three tricks.
+        let deltas = score_deltas(
+            tricks(7, Suit::Hearts),
+            0,
+            None,
+            &TEAMS,
+            &[4, 2, 3, 1],
+            2,
+            true,
+        );
+        assert_eq!(deltas, vec![200, 30]);
+
+        // Seven hearts set by a trick.
+        let deltas = score_deltas(
+            tricks(7, Suit::Hearts),
+            0,
+            None,
+            &TEAMS,
+            &[3, 2, 3, 2],
+            2,
+            true,
+        );
+        assert_eq!(deltas, vec![-200, 40]);
+    }
+
+    #[test]
+    fn defenders_score_nothing_against_misere() {
+        let deltas = score_deltas(Bid::Mis, 1, None, &TEAMS, &[3, 0, 4, 0], 2, true);
+        assert_eq!(deltas, vec![0, 250]);
+
+        let deltas = score_deltas(Bid::OpenMis, 1, None, &TEAMS, &[0, 1, 0, 0], 2, true);
+        assert_eq!(deltas, vec![0, -500]);
+    }
+
+    #[test]
+    fn slams_score_250_beneath_250() {
+        let slam = |bid| score_deltas(bid, 0, None, &TEAMS, &[6, 0, 4, 0], 2, true)[0];
+        assert_eq!(slam(tricks(7, Suit::Diamonds)), 250);
+        assert_eq!(slam(tricks(8, Suit::Spades)), 250);
+        assert_eq!(slam(tricks(8, Suit::Clubs)), 260);
+
+        let no_bonus = score_deltas(
+            tricks(7, Suit::Diamonds),
+            0,
+            None,
+            &TEAMS,
+            &[6, 0, 4, 0],
+            2,
+            false,
+        );
+        assert_eq!(no_bonus[0], 180);
+    }
+
+    #[test]
+    fn matches_are_won_at_500_and_lost_at_minus_500() {
+        // Reaching the target only wins by making a contract.
+        assert_eq!(match_winner(&[520, 100], &[0], 500), Some(0));
+        assert_eq!(match_winner(&[520, 100], &[1], 500), None);
+        assert_eq!(match_winner(&[520, 100], &[], 500), None);
+
+        // Falling to the negative of the target hands the match to the other team.
+        assert_eq!(match_winner(&[-500, 100], &[], 500), Some(1));
+        assert_eq!(match_winner(&[-480, 100], &[], 500), None);
+    }
+}
@@ -0,0 +1,828 @@
+// Information-set Monte Carlo search, used by the stronger computer players. A player can't see the
+// other hands, so each simulation starts by dealing the cards they can't see at random, in a way
+// that agrees with what they have seen: the cards already played, the suits each player has shown
+// out of, and the trump suits each player has bid. The hand is then played out under the real rules,
+// using the same deck, legality and trick-winner logic as the server, and the results of many such
+// simulations decide the bid, discards or play.
+//
+// Plays are chosen by a tree search over the moves made in the simulated hands. Bids and discards
+// don't use the tree search: they're chosen by flat Monte Carlo, comparing the average outcomes of
+// simulated hands played out by rules of thumb from each candidate.
+
+use crate::api;
+use crate::bots;
+use crate::scoring;
+use crate::tricks;
+use crate::types::*;
+
+use rand::rngs::ThreadRng;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::collections::HashSet;
+
+// How strongly the tree search favours moves it has tried less often.
+const EXPLORATION: f64 = 0.7;
+
+// How many deals are tried to find one that agrees with every player's bid, before settling for
+// one that only agrees with their plays.
+const DEAL_ATTEMPTS: usize = 20;
+
+// The number of trumps a player is assumed to have held in a suit they bid.
+const BID_SUIT_LENGTH: usize = 3;
+
+// The number of weakest (or for miseres, strongest) cards that discards are chosen from.
+const DISCARD_CANDIDATES: usize = 7;
+
+// The share of a player's reward that comes from winning or defeating the contract. The rest comes
+// from the tricks their side has won.
+const CONTRACT_REWARD: f64 = 0.7;
+
+// Returns the bid with the best expected score, judged by simulating the hand under each contract
+// the player could bid. Passes if no bid is expected to score.
+pub fn choose_bid(
+    game_history: &api::GameHistory,
+    player_index: usize,
+    rules: Rules,
+    simulations: usize,
+) -> Option<Bid> {
+    let bid_options = game_history
+        .bidding_history
+        .as_ref()?
+        .bid_options
+        .as_ref()?;
+    let knowledge = Knowledge::before_play(game_history, player_index, rules, &[]);
+    let mut rng = rand::thread_rng();
+
+    // The contracts worth simulating: each trump suit that can be bid, and misere.
+    let mut contracts = Vec::new();
+    for bid in bid_options {
+        let contract = match bid {
+            Bid::Tricks(_, suit) => Bid::Tricks(6, *suit),
+            Bid::Mis => Bid::Mis,
+            _ => continue,
+        };
+        if !contracts.contains(&contract) {
+            contracts.push(contract);
+        }
+    }
+    if contracts.is_empty() {
+        return Some(Bid::Pass);
+    }
+
+    // The number of simulated hands in which the player's side won each number of tricks.
+    let simulations = (simulations / contracts.len()).max(1);
+    let trick_counts = contracts
+        .iter()
+        .map(|&contract| {
+            let mut counts = [0; 11];
+            for _ in 0..simulations {
+                let mut hands = knowledge.deal(&mut rng);
+
+                // Take the kitty, which is set aside, and discard the weakest cards.
+                let mut held_cards = hands[player_index].clone();
+                held_cards.extend(hands[rules.player_count].iter());
+                let discarded = bots::weakest_cards(tricks::bid_trumps(contract), &held_cards, 3);
+                held_cards.retain(|card| !discarded.contains(card));
+                hands[player_index] = held_cards;
+                hands.truncate(rules.player_count);
+
+                let mut world = World::new_hand(hands, contract, player_index, rules);
+                world.play_out();
+                counts[world.team_tricks[world.teams[player_index]]] += 1;
+            }
+            counts
+        })
+        .collect::<Vec<_>>();
+
+    // The chance of making the given contract.
+    let chance_made = |bid: Bid| {
+        let (contract, made) = match bid {
+            Bid::Tricks(count, suit) => (Bid::Tricks(6, suit), count..=10),
+            _ => (bid, 0..=0),
+        };
+        let index = contracts.iter().position(|&c| c == contract)?;
+        let made_count = made
+            .map(|tricks| trick_counts[index][tricks])
+            .sum::<usize>();
+        Some(made_count as f64 / simulations as f64)
+    };
+
+    // A contract scores its value when it is made, and loses it otherwise.
+    let expected_score = |bid: Bid| {
+        chance_made(bid).map(|chance| scoring::bid_value(bid) as f64 * (2.0 * chance - 1.0))
+    };
+
+    let best = bid_options
+        .iter()
+        .filter_map(|&bid| Some((bid, expected_score(bid)?)))
+        .filter(|&(_, score)| score > 0.0)
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(bid, _)| bid);
+
+    Some(best.unwrap_or(Bid::Pass))
+}
+
+// Returns the three cards to discard from the player's hand and kitty. The candidates are weighed
+// against each other by simulating the hand after each discard, favouring the most promising.
+pub fn choose_discard(
+    game_history: &api::GameHistory,
+    player_index: usize,
+    rules: Rules,
+    simulations: usize,
+) -> Option<Vec<Card>> {
+    let winning_bid_history = game_history.winning_bid_history.as_ref()?;
+    let kitty = winning_bid_history.kitty.as_ref()?;
+    let contract = winning_bid_history.winning_bid;
+    let trumps = tricks::bid_trumps(contract);
+    let knowledge = Knowledge::before_play(game_history, player_index, rules, kitty);
+    let mut rng = rand::thread_rng();
+
+    let mut held_cards = game_history.hand.clone();
+    held_cards.extend(kitty.iter());
+
+    // Discards are chosen from the weakest cards, or for miseres the strongest.
+    let mut pool = held_cards.clone();
+    pool.sort_by_key(|&card| tricks::play_strength(trumps, tricks::card_play(trumps, card)));
+    if matches!(contract, Bid::Mis | Bid::OpenMis) {
+        pool.reverse();
+    }
+    pool.truncate(DISCARD_CANDIDATES);
+
+    let mut candidates = Vec::new();
+    for i in 0..pool.len() {
+        for j in i + 1..pool.len() {
+            for k in j + 1..pool.len() {
+                candidates.push(Candidate::new(vec![pool[i], pool[j], pool[k]]));
+            }
+        }
+    }
+
+    for simulation in 0..simulations {
+        let candidate = candidates
+            .iter_mut()
+            .max_by(|a, b| a.priority(simulation).total_cmp(&b.priority(simulation)))
+            .unwrap();
+
+        let mut hands = knowledge.deal(&mut rng);
+        hands[player_index] = held_cards
+            .iter()
+            .copied()
+            .filter(|card| !candidate.discarded.contains(card))
+            .collect();
+        hands.truncate(rules.player_count);
+
+        let mut world = World::new_hand(hands, contract, player_index, rules);
+        world.play_out();
+        candidate.visits += 1.0;
+        candidate.total_reward += world.reward(player_index);
+    }
+
+    candidates
+        .into_iter()
+        .max_by(|a, b| a.visits.total_cmp(&b.visits))
+        .map(|candidate| candidate.discarded)
+}
+
+// Returns the play to make, found by a tree search over the plays made in simulated hands.
+pub fn choose_play(
+    game_history: &api::GameHistory,
+    player_index: usize,
+    rules: Rules,
+    simulations: usize,
+) -> Option<Play> {
+    let play_options = game_history.plays_history.as_ref()?.play_options.as_ref()?;
+    if play_options.len() == 1 {
+        return play_options.first().copied();
+    }
+
+    let knowledge = Knowledge::during_play(game_history, player_index, rules)?;
+    let mut rng = rand::thread_rng();
+
+    // The root is the current position, before the player has made their play.
+    let mut nodes = vec![Node::new(player_index, None)];
+    for _ in 0..simulations {
+        let mut world = World::during_play(knowledge.deal(&mut rng), game_history, rules)?;
+        let mut path = Vec::new();
+        let mut node_index = 0;
+
+        // Descend through the plays already tried, until reaching one that hasn't been.
+        while !world.finished {
+            let legal_plays = world.legal_plays();
+            let current_index = world.current_index;
+            let children = nodes[node_index]
+                .children
+                .iter()
+                .copied()
+                .filter(|&c| {
+                    nodes[c].player_index == current_index
+                        && legal_plays.contains(&nodes[c].play.unwrap())
+                })
+                .collect::<Vec<_>>();
+            for &c in &children {
+                nodes[c].availability += 1.0;
+            }
+
+            let untried = legal_plays
+                .iter()
+                .copied()
+                .filter(|&play| !children.iter().any(|&c| nodes[c].play == Some(play)))
+                .collect::<Vec<_>>();
+            if let Some(&play) = untried.choose(&mut rng) {
+                nodes.push(Node::new(current_index, Some(play)));
+                let child_index = nodes.len() - 1;
+                nodes[node_index].children.push(child_index);
+                world.play(play);
+                path.push(child_index);
+                break;
+            }
+
+            // Invariant: every legal play has been tried, so there is at least one child.
+            node_index = children
+                .into_iter()
+                .max_by(|&a, &b| nodes[a].priority().total_cmp(&nodes[b].priority()))
+                .unwrap();
+            world.play(nodes[node_index].play.unwrap());
+            path.push(node_index);
+        }
+
+        world.play_out();
+        for node_index in path {
+            let node = &mut nodes[node_index];
+            node.visits += 1.0;
+            node.total_reward += world.reward(node.player_index);
+        }
+    }
+
+    nodes[0]
+        .children
+        .iter()
+        .max_by(|&&a, &&b| nodes[a].visits.total_cmp(&nodes[b].visits))
+        .and_then(|&c| nodes[c].play)
+}
+
+// A possible set of discards, and how it has fared in simulations.
+struct Candidate {
+    discarded: Vec<Card>,
+    visits: f64,
+    total_reward: f64,
+}
+
+impl Candidate {
+    fn new(discarded: Vec<Card>) -> Self {
+        Candidate {
+            discarded,
+            visits: 0.0,
+            total_reward: 0.0,
+        }
+    }
+
+    // Returns how promising the candidate is to simulate next. Untried candidates come first.
+    fn priority(&self, simulations: usize) -> f64 {
+        if self.visits == 0.0 {
+            return f64::INFINITY;
+        }
+
+        self.total_reward / self.visits
+            + EXPLORATION * ((simulations as f64).ln() / self.visits).sqrt()
+    }
+}
+
+// A play in the search tree, and how it has fared in the simulations that reached it.
+struct Node {
+    // The player who made the play.
+    player_index: usize,
+
+    // None for the root.
+    play: Option<Play>,
+
+    children: Vec<usize>,
+
+    visits: f64,
+
+    // The sum of the rewards for the player who made the play.
+    total_reward: f64,
+
+    // The number of simulations in which the play could have been made.
+    availability: f64,
+}
+
+impl Node {
+    fn new(player_index: usize, play: Option<Play>) -> Self {
+        Node {
+            player_index,
+            play,
+            children: Vec::new(),
+            visits: 0.0,
+            total_reward: 0.0,
+            availability: 1.0,
+        }
+    }
+
+    // Returns how promising the play is to explore next.
+    fn priority(&self) -> f64 {
+        self.total_reward / self.visits
+            + EXPLORATION * (self.availability.ln() / self.visits).sqrt()
+    }
+}
+
+// What a player knows about where the cards are.
+struct Knowledge {
+    player_index: usize,
+
+    // The cards in the player's hand.
+    hand: Vec<Card>,
+
+    // The cards the player can't see: the other hands, and any cards set aside (e.g. the kitty).
+    unseen: Vec<Card>,
+
+    // The number of unseen cards in each player's hand. Zero for the player themselves.
+    hidden_counts: Vec<usize>,
+
+    // The cards known to be in each player's hand, other than the player themselves (i.e. an open
+    // misere hand).
+    known_cards: Vec<Vec<Card>>,
+
+    // The trump suit used to tell which suit each card belongs to.
+    trumps: BidSuit,
+
+    // The suits each player has shown they don't hold.
+    voids: Vec<HashSet<Suit>>,
+
+    // The trump suit each player has bid, if any, and how many of its trumps they are assumed to
+    // still hold.
+    bid_suits: Vec<Option<(Suit, usize)>>,
+}
+
+impl Knowledge {
+    // Returns what the player knows before the first trick, having seen the given cards from the
+    // kitty.
+    fn before_play(
+        game_history: &api::GameHistory,
+        player_index: usize,
+        rules: Rules,
+        kitty: &[Card],
+    ) -> Self {
+        let seen = game_history
+            .hand
+            .iter()
+            .chain(kitty)
+            .collect::<HashSet<_>>();
+        let hidden_counts = (0..rules.player_count)
+            .map(|j| if j == player_index { 0 } else { 10 })
+            .collect::<Vec<_>>();
+
+        Knowledge {
+            player_index,
+            hand: game_history.hand.clone(),
+            unseen: rules
+                .deck_cards()
+                .into_iter()
+                .filter(|card| !seen.contains(card))
+                .collect(),
+            hidden_counts,
+            known_cards: vec![Vec::new(); rules.player_count],
+            trumps: BidSuit::NoTrumps,
+            voids: vec![HashSet::new(); rules.player_count],
+            bid_suits: bid_suits(game_history, player_index, &[]),
+        }
+    }
+
+    // Returns what the player knows partway through the hand.
+    fn during_play(
+        game_history: &api::GameHistory,
+        player_index: usize,
+        rules: Rules,
+    ) -> Option<Self> {
+        let plays_history = game_history.plays_history.as_ref()?;
+        let winning_bid_history = game_history.winning_bid_history.as_ref();
+        let trumps = winning_bid_history
+            .map(|h| tricks::bid_trumps(h.winning_bid))
+            .unwrap_or(BidSuit::NoTrumps);
+
+        // The cards each player has played, and the suits they have shown out of.
+        let mut played_cards = vec![Vec::new(); rules.player_count];
+        let mut voids = vec![HashSet::new(); rules.player_count];
+        let current_leader_index = bots::trick_leader(
+            &plays_history.current_trick,
+            plays_history.currently_playing_player_index,
+            plays_history.inactive_player_index,
+        );
+        let all_tricks = plays_history
+            .played_tricks
+            .iter()
+            .map(|t| (Some(t.leader_index), &t.plays))
+            .chain([(current_leader_index, &plays_history.current_trick)]);
+        for (leader_index, plays) in all_tricks {
+            let led_suit = leader_index
+                .and_then(|i| plays[i])
+                .map(|play| tricks::effective_suit(trumps, play));
+            for (j, play) in plays.iter().enumerate() {
+                let Some(play) = *play else {
+                    continue;
+                };
+
+                played_cards[j].push(tricks::play_card(play));
+                if led_suit.is_some_and(|suit| tricks::effective_suit(trumps, play) != suit) {
+                    voids[j].insert(led_suit.unwrap());
+                }
+            }
+        }
+
+        // An open misere hand is shown to everyone but the bidder.
+        let mut known_cards = vec![Vec::new(); rules.player_count];
+        if let (Some(h), Some(open_hand)) = (winning_bid_history, &plays_history.open_hand) {
+            known_cards[h.winning_bidder_index] = open_hand.clone();
+        }
+
+        // The bidder knows which cards they discarded.
+        let discarded = winning_bid_history
+            .and_then(|h| h.discarded.clone())
+            .unwrap_or_default();
+
+        let seen = game_history
+            .hand
+            .iter()
+            .chain(played_cards.iter().flatten())
+            .chain(known_cards.iter().flatten())
+            .chain(&discarded)
+            .collect::<HashSet<_>>();
+        let hidden_counts = (0..rules.player_count)
+            .map(|j| {
+                if j == player_index {
+                    0
+                } else {
+                    plays_history.hand_sizes[j] - known_cards[j].len()
+                }
+            })
+            .collect::<Vec<_>>();
+
+        Some(Knowledge {
+            player_index,
+            hand: game_history.hand.clone(),
+            unseen: rules
+                .deck_cards()
+                .into_iter()
+                .filter(|card| !seen.contains(card))
+                .collect(),
+            hidden_counts,
+            known_cards,
+            trumps,
+            voids,
+            bid_suits: bid_suits(game_history, player_index, &played_cards),
+        })
+    }
+
+    // Deals the unseen cards at random. Returns a hand for each player, followed by the cards set
+    // aside. Prefers deals that agree with every player's bid.
+    fn deal(&self, rng: &mut ThreadRng) -> Vec<Vec<Card>> {
+        let mut fallback = None;
+        for _ in 0..DEAL_ATTEMPTS {
+            let Some(hands) = self.try_deal(rng, true) else {
+                continue;
+            };
+            if self.agrees_with_bids(&hands) {
+                return hands;
+            }
+            fallback.get_or_insert(hands);
+        }
+
+        // Every player's voids can always be respected by a real deal, but the random deal might
+        // paint itself into a corner.
+        fallback.unwrap_or_else(|| self.try_deal(rng, false).unwrap())
+    }
+
+    // Deals the unseen cards at random, giving each player as many as they hold. Returns None if
+    // the deal runs out of places to put a card without breaking a void.
+    fn try_deal(&self, rng: &mut ThreadRng, respect_voids: bool) -> Option<Vec<Vec<Card>>> {
+        let player_count = self.hidden_counts.len();
+
+        // The last slot holds the cards set aside.
+        let mut remaining = self.hidden_counts.clone();
+        remaining.push(self.unseen.len() - self.hidden_counts.iter().sum::<usize>());
+        let mut hands = vec![Vec::new(); player_count + 1];
+
+        let mut cards = self.unseen.clone();
+        cards.shuffle(rng);
+        for card in cards {
+            let suit = card_suit(self.trumps, card);
+            let allowed = |j: usize| {
+                j == player_count
+                    || !respect_voids
+                    || suit.is_none_or(|suit| !self.voids[j].contains(&suit))
+            };
+
+            // Pick a slot with room for the card, in proportion to how much room it has.
+            let total = (0..=player_count)
+                .filter(|&j| allowed(j))
+                .map(|j| remaining[j])
+                .sum::<usize>();
+            if total == 0 {
+                return None;
+            }
+
+            let mut pick = rng.gen_range(0..total);
+            let slot = (0..=player_count)
+                .filter(|&j| allowed(j))
+                .find(|&j| {
+                    if pick < remaining[j] {
+                        return true;
+                    }
+                    pick -= remaining[j];
+                    false
+                })
+                .unwrap();
+            hands[slot].push(card);
+            remaining[slot] -= 1;
+        }
+
+        hands[self.player_index] = self.hand.clone();
+        for (hand, known_cards) in hands.iter_mut().zip(&self.known_cards) {
+            hand.extend(known_cards.iter());
+        }
+
+        Some(hands)
+    }
+
+    // Returns whether every player holds enough trumps of the suit they bid.
+    fn agrees_with_bids(&self, hands: &[Vec<Card>]) -> bool {
+        self.bid_suits.iter().zip(hands).all(|(bid_suit, hand)| {
+            let Some((suit, length)) = *bid_suit else {
+                return true;
+            };
+            trump_count(suit, hand) >= length
+        })
+    }
+}
+
+// Returns the trump suit each other player has bid, if any, and how many of its trumps they are
+// assumed to still hold given the cards they have played.
+fn bid_suits(
+    game_history: &api::GameHistory,
+    player_index: usize,
+    played_cards: &[Vec<Card>],
+) -> Vec<Option<(Suit, usize)>> {
+    // The auction's bids move to the winning bid history once the bidding is won.
+    let bids = match (
+        &game_history.winning_bid_history,
+        &game_history.bidding_history,
+    ) {
+        (Some(winning_bid_history), _) => &winning_bid_history.bids,
+        (None, Some(bidding_history)) => &bidding_history.bids,
+        (None, None) => return Vec::new(),
+    };
+
+    bids.iter()
+        .enumerate()
+        .map(|(j, bid)| match bid {
+            Some(Bid::Tricks(_, BidSuit::Suit(suit))) if j != player_index => {
+                let played = played_cards
+                    .get(j)
+                    .map_or(0, |cards| trump_count(*suit, cards));
+                Some((*suit, BID_SUIT_LENGTH.saturating_sub(played)))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+// Returns the number of cards that would be trumps in the given suit.
+fn trump_count(suit: Suit, cards: &[Card]) -> usize {
+    cards
+        .iter()
+        .filter(|&&card| card_suit(BidSuit::Suit(suit), card) == Some(suit))
+        .count()
+}
+
+// Returns the suit the given card belongs to for the purposes of play, or None for the joker
+// without trumps (which can take any suit).
+fn card_suit(trumps: BidSuit, card: Card) -> Option<Suit> {
+    match (card, trumps) {
+        (Card::SuitedCard(card), _) => Some(tricks::effective_suit(trumps, Play::SuitedCard(card))),
+        (Card::Joker, BidSuit::Suit(suit)) => Some(suit),
+        (Card::Joker, BidSuit::NoTrumps) => None,
+    }
+}
+
+// A simulated hand, played out under the real rules.
+#[derive(Clone)]
+struct World {
+    rules: Rules,
+    hands: Vec<Vec<Card>>,
+    trumps: BidSuit,
+
+    // The winning bid and its bidder. None if the hand is played after every player passed.
+    contract: Option<(Bid, usize)>,
+
+    // The side each player is on. A called partner is on the bidder's side.
+    teams: Vec<usize>,
+
+    inactive_player_index: Option<usize>,
+
+    current_trick: Vec<Option<Play>>,
+    leader_index: usize,
+    current_index: usize,
+
+    // The number of tricks won by each side.
+    team_tricks: Vec<usize>,
+
+    finished: bool,
+}
+
+impl World {
+    // Returns a hand about to be played under the given contract, once the bidder has used the
+    // kitty. Any partner call is made by rules of thumb.
+    fn new_hand(hands: Vec<Vec<Card>>, contract: Bid, bidder_index: usize, rules: Rules) -> Self {
+        let trumps = tricks::bid_trumps(contract);
+
+        let partner_index = if rules.called_partner() && matches!(contract, Bid::Tricks(_, _)) {
+            let called_card = bots::strongest_missing_card(trumps, &hands[bidder_index], rules);
+            hands.iter().position(|hand| hand.contains(&called_card))
+        } else {
+            None
+        };
+
+        // A misere bidder's partner sits out.
+        let inactive_player_index = matches!(contract, Bid::Mis | Bid::OpenMis)
+            .then(|| {
+                (0..rules.player_count).find(|&j| {
+                    j != bidder_index && rules.team_index(j) == rules.team_index(bidder_index)
+                })
+            })
+            .flatten();
+
+        World {
+            rules,
+            hands,
+            trumps,
+            contract: Some((contract, bidder_index)),
+            teams: teams(rules, bidder_index, partner_index),
+            inactive_player_index,
+            current_trick: vec![None; rules.player_count],
+            leader_index: bidder_index,
+            current_index: bidder_index,
+            team_tricks: vec![0; rules.player_count],
+            finished: false,
+        }
+    }
+
+    // Returns the hand in progress, with the given hands dealt.
+    fn during_play(
+        hands: Vec<Vec<Card>>,
+        game_history: &api::GameHistory,
+        rules: Rules,
+    ) -> Option<Self> {
+        let plays_history = game_history.plays_history.as_ref()?;
+        let winning_bid_history = game_history.winning_bid_history.as_ref();
+        let contract = winning_bid_history.map(|h| (h.winning_bid, h.winning_bidder_index));
+        let hands = hands
+            .into_iter()
+            .take(rules.player_count)
+            .collect::<Vec<_>>();
+
+        // A called partner is known once their card has been played. Until then, it is whoever
+        // holds the called card.
+        let partner_index = plays_history.partner_index.or_else(|| {
+            let called_card = winning_bid_history?.called_card?;
+            hands.iter().position(|hand| hand.contains(&called_card))
+        });
+        let teams = match contract {
+            Some((_, bidder_index)) => teams(rules, bidder_index, partner_index),
+            None => (0..rules.player_count)
+                .map(|j| rules.team_index(j))
+                .collect(),
+        };
+
+        let mut team_tricks = vec![0; rules.player_count];
+        for trick in &plays_history.played_tricks {
+            team_tricks[teams[trick.winner_index]] += 1;
+        }
+
+        let current_index = plays_history.currently_playing_player_index;
+        let leader_index = bots::trick_leader(
+            &plays_history.current_trick,
+            current_index,
+            plays_history.inactive_player_index,
+        )
+        .unwrap_or(current_index);
+
+        Some(World {
+            rules,
+            hands,
+            trumps: contract.map_or(BidSuit::NoTrumps, |(bid, _)| tricks::bid_trumps(bid)),
+            contract,
+            teams,
+            inactive_player_index: plays_history.inactive_player_index,
+            current_trick: plays_history.current_trick.clone(),
+            leader_index,
+            current_index,
+            team_tricks,
+            finished: false,
+        })
+    }
+
+    fn led_suit(&self) -> Option<Suit> {
+        self.current_trick[self.leader_index].map(|play| tricks::effective_suit(self.trumps, play))
+    }
+
+    fn misere_bidder_index(&self) -> Option<usize> {
+        match self.contract {
+            Some((Bid::Mis | Bid::OpenMis, bidder_index)) => Some(bidder_index),
+            _ => None,
+        }
+    }
+
+    // Returns the plays the current player can make.
+    fn legal_plays(&self) -> Vec<Play> {
+        tricks::legal_plays(
+            self.trumps,
+            self.rules.no_trumps_joker_void_only,
+            &self.hands[self.current_index],
+            self.led_suit(),
+        )
+    }
+
+    // Makes the given play for the current player, completing the trick if it is the last play.
+    fn play(&mut self, play: Play) {
+        let hand = &mut self.hands[self.current_index];
+        let card_index = hand
+            .iter()
+            .position(|&c| c == tricks::play_card(play))
+            .unwrap();
+        hand.remove(card_index);
+        self.current_trick[self.current_index] = Some(play);
+
+        // The next player to play, skipping any player sitting out.
+        let player_count = self.rules.player_count;
+        self.current_index = (self.current_index + 1) % player_count;
+        if Some(self.current_index) == self.inactive_player_index {
+            self.current_index = (self.current_index + 1) % player_count;
+        }
+        if self.current_index != self.leader_index {
+            return;
+        }
+
+        let winner_index =
+            tricks::trick_winner(self.trumps, self.led_suit().unwrap(), &self.current_trick);
+        self.team_tricks[self.teams[winner_index]] += 1;
+        self.current_trick = vec![None; player_count];
+        self.leader_index = winner_index;
+        self.current_index = winner_index;
+
+        // The hand is over, possibly because a misere bidder has won a trick.
+        self.finished =
+            self.hands[winner_index].is_empty() || self.misere_bidder_index() == Some(winner_index);
+    }
+
+    // Plays out the rest of the hand by rules of thumb.
+    fn play_out(&mut self) {
+        while !self.finished {
+            let teams = &self.teams;
+            let play = bots::rule_of_thumb_play(
+                self.trumps,
+                &self.legal_plays(),
+                &self.current_trick,
+                self.current_trick[self.leader_index].map(|_| self.leader_index),
+                self.misere_bidder_index(),
+                self.current_index,
+                |a, b| teams[a] == teams[b],
+            );
+            self.play(play);
+        }
+    }
+
+    // Returns how well the finished hand went for the given player, between 0 and 1. Players are
+    // rewarded for making or defeating the contract, and for the tricks their side won.
+    fn reward(&self, player_index: usize) -> f64 {
+        let team = self.teams[player_index];
+        let trick_share = self.team_tricks[team] as f64 / 10.0;
+
+        let Some((bid, bidder_index)) = self.contract else {
+            return trick_share;
+        };
+        let bidding_team = self.teams[bidder_index];
+        let made = match bid {
+            Bid::Tricks(count, _) => self.team_tricks[bidding_team] >= count,
+            _ => self.team_tricks[bidding_team] == 0,
+        };
+
+        let contract_reward = if (team == bidding_team) == made {
+            1.0
+        } else {
+            0.0
+        };
+        CONTRACT_REWARD * contract_reward + (1.0 - CONTRACT_REWARD) * trick_share
+    }
+}
+
+// Returns the side each player is on. A called partner joins the bidder's side.
+fn teams(rules: Rules, bidder_index: usize, partner_index: Option<usize>) -> Vec<usize> {
+    (0..rules.player_count)
+        .map(|j| {
+            if Some(j) == partner_index {
+                rules.team_index(bidder_index)
+            } else {
+                rules.team_index(j)
+            }
+        })
+        .collect()
+}
@@ -1,27 +1,58 @@
 // The top-level instance of a 500s session. Coordinates the lobby, bidding and gameplay for one
 // match.
 
+use std::collections::{HashMap, VecDeque};
 use std::debug_assert;
 
 use crate::api;
+use crate::bots;
 use crate::events;
 use crate::events::ClientEventPayload::Connect;
 use crate::events::ClientEventPayload::Disconnect;
 use crate::events::ClientEventPayload::Step;
 use crate::stages;
+use crate::types;
 
-use log::info;
+use log::{error, info};
+use tokio::time;
+use unique_id::random::RandomGenerator;
+use unique_id::Generator;
+
+// The most characters a chat message can have.
+const MAX_CHAT_LENGTH: usize = 200;
+
+// The number of recent chat messages kept in each history.
+const RECENT_CHAT_LENGTH: usize = 20;
+
+// Each client can send at most this many chat messages and reactions in any window of this length.
+const CHAT_RATE_LIMIT: usize = 5;
+const CHAT_RATE_WINDOW: time::Duration = time::Duration::from_secs(10);
 
 pub struct Session {
     event_rx: events::ClientEventReceiver,
     clients: events::ClientMap,
 
+    // The code of the session's room, and where to report that the room has emptied.
+    room_code: String,
+    room_empty_tx: events::RoomEmptySender,
+
+    // The house rules the match is played under.
+    rules: types::Rules,
+
     // The client IDs and state histories for each playing player. There can be clients who aren't
     // players, for example when they are unsuccessfully trying to join a full game.
-    //
-    // TODO: support leaving and rejoining.
     players: Vec<(events::ClientId, api::History)>,
 
+    // When each disconnected player's seat will be given up (aborting the match), unless they
+    // resume it first.
+    disconnect_deadlines: HashMap<events::ClientId, time::Instant>,
+
+    // How long a disconnected player has to resume their seat.
+    grace_period: time::Duration,
+
+    // When each client sent their recent chat messages and reactions, from oldest to newest.
+    chat_times: HashMap<events::ClientId, VecDeque<time::Instant>>,
+
     // The major stage of the session (e.g. lobby, bidding, playing tricks) that we are currently
     // in.
     //
@@ -31,18 +62,58 @@ pub struct Session {
 }
 
 impl Session {
-    pub fn new(event_rx: events::ClientEventReceiver) -> Self {
+    pub fn new(
+        event_rx: events::ClientEventReceiver,
+        room_code: String,
+        room_empty_tx: events::RoomEmptySender,
+        rules: types::Rules,
+        deck: types::Deck,
+    ) -> Self {
         Self {
             event_rx,
             clients: events::ClientMap::new(),
+            room_code,
+            room_empty_tx,
+            rules,
             players: Vec::new(),
-            stage: Some(Box::new(stages::Lobby::new(0))),
+            disconnect_deadlines: HashMap::new(),
+            grace_period: time::Duration::from_secs(rules.reconnect_grace_secs),
+            chat_times: HashMap::new(),
+            stage: Some(Box::new(stages::Lobby::new(0, rules, deck))),
         }
     }
 
     pub async fn run_main_loop(&mut self) {
         loop {
-            let Some(event) = self.event_rx.recv().await else {
+            // Wait for the next event, for the earliest disconnected player to run out of time, or
+            // for the current turn to run out.
+            let deadline = self.disconnect_deadlines.values().min().copied();
+            let turn_deadline = self.stage.as_ref().unwrap().turn_deadline();
+            let event = tokio::select! {
+                event = self.event_rx.recv() => event,
+                _ = time::sleep_until(deadline.unwrap_or_else(time::Instant::now)),
+                    if deadline.is_some() =>
+                {
+                    // Once the match is over, there's nothing left to abort.
+                    if self.match_in_progress() {
+                        info!("Disconnected player didn't resume their seat - aborting.");
+                        self.abort_match("Player disconnected");
+                    } else {
+                        self.disconnect_deadlines.clear();
+                    }
+                    self.report_if_empty();
+                    continue;
+                }
+                _ = time::sleep_until(turn_deadline.unwrap_or_else(time::Instant::now)),
+                    if turn_deadline.is_some() =>
+                {
+                    self.take_default_step();
+                    self.run_bots();
+                    continue;
+                }
+            };
+
+            let Some(event) = event else {
                 info!("All clients dropped - exiting.");
                 return;
             };
@@ -63,68 +134,84 @@ impl Session {
                     id,
                     payload: Disconnect,
                 } => {
+                    // Before the match has started, the player just leaves the lobby.
+                    if self.player_index(id).is_some() && !self.match_started() {
+                        info!("Player [client {}] disconnected from the lobby.", id);
+                        self.process_step(id, &api::Step::Quit);
+                    }
+
                     self.clients.remove_client(id);
+                    self.chat_times.remove(id);
 
-                    if self.player_index(id).is_some() {
+                    // While the match is being played, hold the player's seat for them, and let
+                    // everyone else know the table is waiting.
+                    if let Some(index) = self.player_index(id).filter(|_| self.match_in_progress())
+                    {
                         info!("Player [client {}] disconnected.", id);
+                        self.disconnect_deadlines
+                            .insert(id.clone(), time::Instant::now() + self.grace_period);
 
-                        // Let everyone else know the game can't continue.
-                        for (player_id, history) in &self.players {
-                            if *player_id == *id {
-                                continue;
-                            }
-
-                            let aborted_history = api::History {
-                                match_history: history.match_history.clone().map(|h| {
-                                    api::MatchHistory {
-                                        match_aborted_reason: Some(
-                                            "Player disconnected".to_string(),
-                                        ),
-                                        ..h
-                                    }
-                                }),
-                                ..history.clone()
-                            };
-                            self.clients.send_event(
-                                player_id,
-                                aborted_history,
-                                api::CurrentState::MatchAborted,
-                            );
+                        for (_, history) in &mut self.players {
+                            // Invariant: all players have lobby history populated.
+                            let lobby_history = history.lobby_history.as_mut().unwrap();
+                            lobby_history.disconnected_player_indices.push(index);
                         }
-
-                        self.stage = Some(Box::new(stages::Aborted {}));
-                        continue;
+                        self.notify_players(api::CurrentState::PlayerDisconnected);
                     }
+
+                    self.report_if_empty();
+                }
+
+                // A client who isn't playing wants to follow the match.
+                events::ClientEvent {
+                    id,
+                    payload: Step(api::Step::Spectate),
+                } => {
+                    self.add_spectator(id);
+                }
+
+                // A player wants a computer player to fill an empty seat.
+                events::ClientEvent {
+                    id,
+                    payload: Step(api::Step::AddBot(team_index, difficulty)),
+                } if !self.match_started() => {
+                    self.add_bot(id, *team_index, *difficulty);
+                }
+
+                // A player is talking to the table.
+                events::ClientEvent {
+                    id,
+                    payload: Step(api::Step::Chat(text)),
+                } => {
+                    self.relay_chat(id, api::ChatContent::Text(text.trim().to_string()));
+                }
+
+                events::ClientEvent {
+                    id,
+                    payload: Step(api::Step::React(reaction)),
+                } => {
+                    self.relay_chat(id, api::ChatContent::Reaction(*reaction));
+                }
+
+                // A new connection is taking over a disconnected player's seat.
+                events::ClientEvent {
+                    id,
+                    payload: Step(api::Step::Resume(token)),
+                } => {
+                    self.resume_player(id, token);
                 }
 
                 // A client has left. This might end the game if they are an active player. We
-                // handle this here because a player can quit from any stage.
+                // handle this here because a player can quit from any stage while the match is
+                // being played.
                 events::ClientEvent {
                     id,
                     payload: Step(api::Step::Quit),
-                } => {
+                } if self.match_in_progress() => {
                     // Active player has left.
                     if self.player_index(id).is_some() {
-                        // Let everyone know the game can't continue.
-                        for (player_id, history) in &self.players {
-                            let error_history = api::History {
-                                match_history: history.match_history.clone().map(|h| {
-                                    api::MatchHistory {
-                                        match_aborted_reason: Some("Player left".to_string()),
-                                        ..h
-                                    }
-                                }),
-                                ..history.clone()
-                            };
-                            self.clients.send_event(
-                                player_id,
-                                error_history,
-                                api::CurrentState::MatchAborted,
-                            );
-                        }
-
                         info!("Player [client {}] left.", id);
-                        self.stage = Some(Box::new(stages::Aborted {}));
+                        self.abort_match("Player left");
                     } else {
                         info!("[client {}] tried to leave without joining.", id);
                         self.clients.send_event(
@@ -144,23 +231,360 @@ impl Session {
                     id,
                     payload: Step(step),
                 } => {
-                    let player_index = self.player_index(id);
-
-                    // Give up and then retake ownership of the stage object.
-                    let stage = self.stage.take();
-                    debug_assert!(stage.is_some());
-                    self.stage = Some(stage.unwrap().process_step(
-                        &mut self.players,
-                        player_index,
-                        &self.clients,
-                        id,
-                        step,
-                    ));
+                    self.process_step(id, step);
                 }
             };
+
+            // Let any bots whose turn it now is take it.
+            self.run_bots();
+        }
+    }
+
+    // Passes the given step to the current stage, and moves on to the stage it returns.
+    fn process_step(&mut self, id: &events::ClientId, step: &api::Step) {
+        let player_index = self.player_index(id);
+
+        // Give up and then retake ownership of the stage object.
+        let stage = self.stage.take();
+        debug_assert!(stage.is_some());
+        self.stage = Some(stage.unwrap().process_step(
+            &mut self.players,
+            player_index,
+            &self.clients,
+            id,
+            step,
+        ));
+
+        // Spectators who take a seat (e.g. by joining the lobby) stop spectating.
+        if self.player_index(id).is_some() {
+            self.clients.remove_spectator(id);
+        }
+    }
+
+    // Takes the default action for the player whose turn has run out.
+    fn take_default_step(&mut self) {
+        let Some((index, step)) = self.stage.as_ref().unwrap().default_step(&self.players) else {
+            return;
+        };
+
+        let id = self.players[index].0.clone();
+        info!("[client {}] ran out of time - taking {:?}.", id, step);
+        self.process_step(&id, &step);
+    }
+
+    // Seats a computer player of the given difficulty on the given team, on behalf of a player in
+    // the lobby. The bot joins through the lobby like any other player.
+    fn add_bot(
+        &mut self,
+        id: &events::ClientId,
+        team_index: usize,
+        difficulty: types::BotDifficulty,
+    ) {
+        let Some(index) = self.player_index(id) else {
+            error!("[client {}] tried to add a bot without joining", id);
+            self.clients.send_event(
+                id,
+                api::History {
+                    error: Some("Join the table before adding bots.".to_string()),
+                    ..Default::default()
+                },
+                api::CurrentState::Error,
+            );
+            return;
+        };
+
+        // Invariant: all players share the same names.
+        let player_names = &self.players[0]
+            .1
+            .lobby_history
+            .as_ref()
+            .unwrap()
+            .player_names;
+        let name = (1..)
+            .map(|i| format!("Bot {}", i))
+            .find(|name| {
+                !player_names
+                    .iter()
+                    .any(|n| n.to_lowercase() == name.to_lowercase())
+            })
+            .unwrap();
+
+        let bot_id = format!("bot-{}", RandomGenerator.next_id());
+        self.clients.add_bot(&bot_id, difficulty);
+        self.process_step(&bot_id, &api::Step::Join(team_index, name));
+
+        if self.player_index(&bot_id).is_none() {
+            error!("[client {}] couldn't add a bot to team {}", id, team_index);
+            self.clients.remove_client(&bot_id);
+            self.clients.send_event(
+                id,
+                api::History {
+                    error: Some("There's no free seat on that team.".to_string()),
+                    ..self.players[index].1.clone()
+                },
+                api::CurrentState::PlayerJoined,
+            );
+            return;
+        }
+
+        info!(
+            "[client {}] added {:?} [client {}] to team {}.",
+            id, difficulty, bot_id, team_index
+        );
+    }
+
+    // Takes the turns of any bots the table is waiting for, until it is waiting for a person.
+    fn run_bots(&mut self) {
+        loop {
+            // Invariant: the stage is only taken while a step is being processed.
+            let stage = self.stage.as_ref().unwrap();
+            let Some((id, step)) =
+                self.players
+                    .iter()
+                    .enumerate()
+                    .find_map(|(i, (id, history))| {
+                        let difficulty = self.clients.bot_difficulty(id)?;
+                        bots::choose_step(
+                            history,
+                            &stage.waiting_state(i),
+                            i,
+                            self.rules,
+                            difficulty,
+                        )
+                        .map(|step| (id.clone(), step))
+                    })
+            else {
+                return;
+            };
+
+            self.process_step(&id, &step);
         }
     }
 
+    // Registers the given client as a spectator, unless they are playing, and sends them the
+    // public view of the match.
+    fn add_spectator(&mut self, id: &events::ClientId) {
+        if self.player_index(id).is_some() {
+            error!("[client {}] tried to spectate while seated", id);
+            self.clients.send_event(
+                id,
+                api::History {
+                    error: Some("Players can't spectate.".to_string()),
+                    ..Default::default()
+                },
+                api::CurrentState::Error,
+            );
+            return;
+        }
+
+        info!("[client {}] is spectating.", id);
+        self.clients.add_spectator(id);
+        self.clients.send_event(
+            id,
+            events::public_history(&self.players),
+            api::CurrentState::Spectating,
+        );
+    }
+
+    // Lets the room manager know the room can be closed, once no client is connected and no seat is
+    // held for a disconnected player.
+    fn report_if_empty(&self) {
+        if !self.clients.is_empty() || !self.disconnect_deadlines.is_empty() {
+            return;
+        }
+
+        info!("Room {} has emptied.", self.room_code);
+        if self.room_empty_tx.send(self.room_code.clone()).is_err() {
+            error!("Session couldn't report its room has emptied.");
+        }
+    }
+
+    // Returns whether the match has started (i.e. the session has left the lobby).
+    fn match_started(&self) -> bool {
+        // Invariant: the stage is only taken while a step is being processed.
+        self.stage.as_ref().unwrap().match_started()
+    }
+
+    // Returns whether the match has started and not yet been won or aborted.
+    fn match_in_progress(&self) -> bool {
+        // Invariant: the stage is only taken while a step is being processed.
+        let stage = self.stage.as_ref().unwrap();
+        stage.match_started() && !stage.match_over()
+    }
+
+    // Hands a disconnected player's seat to the given client, if the token matches their seat. The
+    // client is sent the full history of that seat.
+    fn resume_player(&mut self, id: &events::ClientId, token: &str) {
+        if self.player_index(id).is_some() {
+            error!("[client {}] tried to resume a seat while seated", id);
+            self.clients.send_event(
+                id,
+                api::History {
+                    error: Some("You already have a seat.".to_string()),
+                    ..Default::default()
+                },
+                api::CurrentState::Error,
+            );
+            return;
+        }
+
+        // Invariant: all players have lobby history populated.
+        let seat = self
+            .players
+            .iter()
+            .position(|(_, history)| history.lobby_history.as_ref().unwrap().resume_token == token);
+        let Some(index) = seat else {
+            error!("[client {}] tried to resume a seat with a bad token", id);
+            self.clients.send_event(
+                id,
+                api::History {
+                    error: Some("No seat has that resume token.".to_string()),
+                    ..Default::default()
+                },
+                api::CurrentState::Error,
+            );
+            return;
+        };
+
+        // The old connection may not have dropped yet (e.g. the client moved networks). Either
+        // way, it no longer holds the seat.
+        let old_id = std::mem::replace(&mut self.players[index].0, id.clone());
+        self.disconnect_deadlines.remove(&old_id);
+        self.clients.remove_spectator(id);
+        info!("[client {}] resumed the seat of [client {}].", id, old_id);
+
+        for (_, history) in &mut self.players {
+            history
+                .lobby_history
+                .as_mut()
+                .unwrap()
+                .disconnected_player_indices
+                .retain(|&i| i != index);
+        }
+        self.notify_players(api::CurrentState::PlayerReconnected);
+    }
+
+    // Adds a player's chat message or reaction to everyone's history, and relays it to the table.
+    fn relay_chat(&mut self, id: &events::ClientId, content: api::ChatContent) {
+        let Some(index) = self.player_index(id) else {
+            error!("[client {}] tried to chat without a seat", id);
+            self.clients.send_event(
+                id,
+                api::History {
+                    error: Some("Only players can chat.".to_string()),
+                    ..Default::default()
+                },
+                api::CurrentState::Error,
+            );
+            return;
+        };
+
+        // Forget messages that have fallen out of the rate limit window.
+        let now = time::Instant::now();
+        let chat_times = self.chat_times.entry(id.clone()).or_default();
+        while chat_times
+            .front()
+            .is_some_and(|&t| now.duration_since(t) >= CHAT_RATE_WINDOW)
+        {
+            chat_times.pop_front();
+        }
+
+        let chat_error = match &content {
+            _ if chat_times.len() >= CHAT_RATE_LIMIT => {
+                Some("You're sending messages too quickly.".to_string())
+            }
+            api::ChatContent::Text(text)
+                if text.is_empty() || text.chars().count() > MAX_CHAT_LENGTH =>
+            {
+                Some(format!(
+                    "Messages must be between 1 and {} characters.",
+                    MAX_CHAT_LENGTH
+                ))
+            }
+            _ => None,
+        };
+
+        if let Some(error) = chat_error {
+            error!("[client {}] sent a rejected chat message", id);
+            // Invariant: the stage is only taken while a step is being processed.
+            let state = self.stage.as_ref().unwrap().waiting_state(index);
+            self.clients.send_event(
+                id,
+                api::History {
+                    error: Some(error),
+                    ..self.players[index].1.clone()
+                },
+                state,
+            );
+            return;
+        }
+        chat_times.push_back(now);
+
+        // Invariant: all players have lobby history populated.
+        let message = api::ChatMessage {
+            player_index: index,
+            name: self.players[index]
+                .1
+                .lobby_history
+                .as_ref()
+                .unwrap()
+                .player_names[index]
+                .clone(),
+            content,
+        };
+        info!("[client {}] sent to the table: {:?}", id, message.content);
+
+        for (_, history) in &mut self.players {
+            history.recent_chat.push(message.clone());
+            let excess = history.recent_chat.len().saturating_sub(RECENT_CHAT_LENGTH);
+            history.recent_chat.drain(..excess);
+        }
+        self.notify_players(api::CurrentState::ChatReceived);
+    }
+
+    // Sends the given state to every connected player and spectator, followed by the state they
+    // should be waiting in.
+    fn notify_players(&self, state: api::CurrentState) {
+        // Invariant: the stage is only taken while a step is being processed.
+        let stage = self.stage.as_ref().unwrap();
+        for (i, (id, history)) in self.players.iter().enumerate() {
+            if self.disconnect_deadlines.contains_key(id) {
+                continue;
+            }
+
+            self.clients.send_event(id, history.clone(), state.clone());
+            self.clients
+                .send_event(id, history.clone(), stage.waiting_state(i));
+        }
+
+        self.clients.send_spectators(&self.players, state);
+        self.clients
+            .send_spectators(&self.players, stage.waiting_state(0));
+    }
+
+    // Lets every connected player and spectator know the match can't continue, and ends it.
+    fn abort_match(&mut self, reason: &str) {
+        for (_, history) in &mut self.players {
+            if let Some(match_history) = history.match_history.as_mut() {
+                match_history.match_aborted_reason = Some(reason.to_string());
+            }
+        }
+
+        for (id, history) in &self.players {
+            if self.disconnect_deadlines.contains_key(id) {
+                continue;
+            }
+
+            self.clients
+                .send_event(id, history.clone(), api::CurrentState::MatchAborted);
+        }
+        self.clients
+            .send_spectators(&self.players, api::CurrentState::MatchAborted);
+
+        self.disconnect_deadlines.clear();
+        self.stage = Some(Box::new(stages::Aborted {}));
+    }
+
     // Returns the index in the player list of the given client ID, if it is present.
     fn player_index(&self, id: &events::ClientId) -> Option<usize> {
         self.players.iter().position(|(i, _)| i == id)
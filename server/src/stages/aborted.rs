@@ -40,4 +40,12 @@ impl super::Stage for Aborted {
 
         self
     }
+
+    fn waiting_state(&self, _player_index: usize) -> api::CurrentState {
+        api::CurrentState::MatchAborted
+    }
+
+    fn match_over(&self) -> bool {
+        true
+    }
 }
@@ -1,46 +1,68 @@
-// The stage of the game where one player makes use of the kitty and possibly
-// declares the suit of the Joker.
+// The stage of the game where one player makes use of the kitty.
+//
+// In five-handed play, the winning bidder of a trick contract then names a card
+// to call the player holding it as their partner. Their identity stays hidden
+// until that card is played.
 
 use crate::api;
+use crate::bots;
 use crate::events;
+use crate::tricks;
 use crate::types::*;
 
-use log::error;
+use log::{error, info};
 use std::collections::HashSet;
 use std::debug_assert;
+use tokio::time;
 
+use super::Playing;
 use super::Stage;
 
 pub struct BidWon {
+    game_index: usize,
+    rules: Rules,
+    deck: Deck,
     winning_bidder_index: usize,
+    winning_bid: Bid,
     kitty: Vec<Card>,
+
+    // Whether we are waiting for the winning bidder to call their partner, once the kitty has been
+    // used.
+    calling_partner: bool,
+
+    // When the current turn runs out, if it is timed.
+    turn_deadline: Option<time::Instant>,
 }
 
 impl BidWon {
     pub fn new(
-        players: &mut Vec<(events::ClientId, api::History)>,
+        players: &mut [(events::ClientId, api::History)],
         clients: &events::ClientMap,
-        winning_bidder_index: usize,
-        winning_bid: Bid,
+        game_index: usize,
+        rules: Rules,
+        deck: Deck,
         kitty: Vec<Card>,
     ) -> Self {
-        // Notify players that the bid has been won.
-        for (id, history) in players.iter_mut() {
-            unwrap_game_history(history).winning_bid_history = Some(api::WinningBidHistory {
-                winning_bidder_index,
-                winning_bid,
-                kitty: None,
-                discarded: None,
-            });
+        // Invariant: all players share the same winning bid, which was populated at the end of the
+        // bidding.
+        let api::WinningBidHistory {
+            winning_bidder_index,
+            winning_bid,
+            ..
+        } = *unwrap_winning_bid_history(&mut players[0].1);
 
+        // Notify players that the bid has been won.
+        for (id, history) in players.iter() {
             clients.send_event(id, history.clone(), api::CurrentState::BidWon);
         }
+        clients.send_spectators(players, api::CurrentState::BidWon);
 
         // Assign the kitty to the winning bidder.
         unwrap_winning_bid_history(&mut players[winning_bidder_index].1).kitty =
             Some(kitty.clone());
 
         // Now notify players that the kitty needs to be used.
+        let turn_deadline = super::start_turn(players, rules.kitty_timeout_secs);
         for (i, (id, history)) in players.iter().enumerate() {
             clients.send_event(
                 id,
@@ -52,17 +74,24 @@ impl BidWon {
                 },
             );
         }
+        clients.send_spectators(players, api::CurrentState::WaitingForTheirKitty);
 
         BidWon {
+            game_index,
+            rules,
+            deck,
             winning_bidder_index,
+            winning_bid,
             kitty,
+            calling_partner: false,
+            turn_deadline,
         }
     }
 }
 
 impl Stage for BidWon {
     fn process_step(
-        self: Box<Self>,
+        mut self: Box<Self>,
         players: &mut Vec<(events::ClientId, api::History)>,
         player_index: Option<usize>,
         clients: &events::ClientMap,
@@ -70,10 +99,12 @@ impl Stage for BidWon {
         step: &api::Step,
     ) -> Box<dyn Stage> {
         // Bail with an error response if this isn't a player.
-        let Some(index) = super::reject_nonplayer(player_index, clients, client_id, step) else { return self; };
+        let Some(index) = super::reject_nonplayer(player_index, clients, client_id, step) else {
+            return self;
+        };
 
         match step {
-            api::Step::DiscardCards(cards) => {
+            api::Step::DiscardCards(cards) if !self.calling_partner => {
                 // Player isn't the bid winner.
                 if index != self.winning_bidder_index {
                     error!(
@@ -149,10 +180,80 @@ impl Stage for BidWon {
                 unwrap_winning_bid_history(&mut players[index].1).discarded =
                     Some(discarded.iter().copied().collect::<Vec<_>>());
 
-                // TODO: handle joker declaration and actual play.
-                for (id, history) in players.iter() {
-                    clients.send_event(id, history.clone(), api::CurrentState::WaitingForTheirPlay);
+                // Trick contracts in five-handed play need a partner to be called.
+                if self.rules.called_partner() && matches!(self.winning_bid, Bid::Tricks(_, _)) {
+                    self.calling_partner = true;
+                    self.turn_deadline = super::start_turn(players, self.rules.kitty_timeout_secs);
+                    for (j, (id, history)) in players.iter().enumerate() {
+                        clients.send_event(id, history.clone(), self.waiting_state(j));
+                    }
+                    clients.send_spectators(players, self.waiting_state(0));
+
+                    return self;
+                }
+
+                return Box::new(Playing::new(
+                    players,
+                    clients,
+                    self.game_index,
+                    self.rules,
+                    self.deck,
+                ));
+            }
+
+            api::Step::CallPartner(card) if self.calling_partner => {
+                // Player isn't the bid winner.
+                if index != self.winning_bidder_index {
+                    error!(
+                        "[client {}] tried to call a partner without winning the bid",
+                        client_id
+                    );
+                    clients.send_event(
+                        client_id,
+                        api::History {
+                            error: Some("You aren't calling a partner.".to_string()),
+                            ..players[index].1.clone()
+                        },
+                        api::CurrentState::WaitingForTheirPartnerCall,
+                    );
+
+                    return self;
+                }
+
+                // Player has named a card that doesn't exist.
+                if !self.rules.deck_cards().contains(card) {
+                    error!(
+                        "[client {}] tried to call a card not in the deck",
+                        client_id
+                    );
+                    clients.send_event(
+                        client_id,
+                        api::History {
+                            error: Some("That card isn't in the deck.".to_string()),
+                            ..players[index].1.clone()
+                        },
+                        api::CurrentState::WaitingForYourPartnerCall,
+                    );
+
+                    return self;
                 }
+
+                // The bidder plays alone if they hold the card, or if it is in the discards.
+                info!("[client {}] called {:?} as their partner.", client_id, card);
+                self.calling_partner = false;
+                for (id, history) in players.iter_mut() {
+                    unwrap_winning_bid_history(history).called_card = Some(*card);
+                    clients.send_event(id, history.clone(), api::CurrentState::PartnerCalled);
+                }
+                clients.send_spectators(players, api::CurrentState::PartnerCalled);
+
+                return Box::new(Playing::new(
+                    players,
+                    clients,
+                    self.game_index,
+                    self.rules,
+                    self.deck,
+                ));
             }
 
             _bad_step => {
@@ -162,11 +263,7 @@ impl Stage for BidWon {
                     clients,
                     client_id,
                     step,
-                    if index == self.winning_bidder_index {
-                        api::CurrentState::WaitingForYourKitty
-                    } else {
-                        api::CurrentState::WaitingForTheirKitty
-                    },
+                    self.waiting_state(index),
                     "after bid won",
                 );
             }
@@ -174,6 +271,46 @@ impl Stage for BidWon {
 
         self
     }
+
+    fn waiting_state(&self, player_index: usize) -> api::CurrentState {
+        match (
+            self.calling_partner,
+            player_index == self.winning_bidder_index,
+        ) {
+            (true, true) => api::CurrentState::WaitingForYourPartnerCall,
+            (true, false) => api::CurrentState::WaitingForTheirPartnerCall,
+            (false, true) => api::CurrentState::WaitingForYourKitty,
+            (false, false) => api::CurrentState::WaitingForTheirKitty,
+        }
+    }
+
+    fn turn_deadline(&self) -> Option<time::Instant> {
+        self.turn_deadline
+    }
+
+    // A bidder who runs out of time calls the highest card they don't hold, or discards their three
+    // lowest cards.
+    fn default_step(
+        &self,
+        players: &[(events::ClientId, api::History)],
+    ) -> Option<(usize, api::Step)> {
+        let trumps = tricks::bid_trumps(self.winning_bid);
+        let hand = &players[self.winning_bidder_index]
+            .1
+            .game_history
+            .as_ref()
+            .unwrap()
+            .hand;
+
+        let step = if self.calling_partner {
+            api::Step::CallPartner(bots::strongest_missing_card(trumps, hand, self.rules))
+        } else {
+            let held_cards = hand.iter().chain(&self.kitty).copied().collect::<Vec<_>>();
+            api::Step::DiscardCards(bots::weakest_cards(trumps, &held_cards, 3))
+        };
+
+        Some((self.winning_bidder_index, step))
+    }
 }
 
 // Convenience functions to extract mutable sub-histories.
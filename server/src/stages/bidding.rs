@@ -4,14 +4,20 @@ use crate::api;
 use crate::events;
 use crate::types::*;
 
-use log::error;
-use rand::seq::SliceRandom;
+use log::{error, info};
 use std::debug_assert;
+use tokio::time;
 
-use super::bid_won;
+use super::BidWon;
+use super::Playing;
 use super::Stage;
 
 pub struct Bidding {
+    // The number of games dealt before this one in the match.
+    game_index: usize,
+    rules: Rules,
+    deck: Deck,
+
     first_bidder_index: usize,
     bids_made: usize,
 
@@ -22,68 +28,58 @@ pub struct Bidding {
     prev_bids: Vec<Option<Bid>>,
 
     highest_bid: Option<Bid>,
+
+    // The number of passes made since the last (non-pass) bid. Used to end the bidding when
+    // passed players may re-enter it.
+    passes_since_bid: usize,
+
+    // When the current bidder's turn runs out, if it is timed.
+    turn_deadline: Option<time::Instant>,
 }
 
 impl Bidding {
     pub fn new(
-        players: &mut Vec<(events::ClientId, api::History)>,
+        players: &mut [(events::ClientId, api::History)],
         clients: &events::ClientMap,
-        first_bidder_index: usize,
+        game_index: usize,
+        rules: Rules,
+        mut deck: Deck,
     ) -> Self {
-        debug_assert_eq!(players.len(), 4);
+        debug_assert_eq!(players.len(), rules.player_count);
 
-        // Populate and shuffle deck.
-        let mut deck = (5..15)
-            .flat_map(|face| {
-                [Suit::Spades, Suit::Clubs, Suit::Diamonds, Suit::Hearts]
-                    .iter()
-                    .map(move |suit| Card::SuitedCard(SuitedCard { face, suit: *suit }))
-            })
-            .chain(vec![
-                Card::SuitedCard(SuitedCard {
-                    face: 4,
-                    suit: Suit::Diamonds,
-                }),
-                Card::SuitedCard(SuitedCard {
-                    face: 4,
-                    suit: Suit::Hearts,
-                }),
-                Card::Joker,
-            ])
-            .collect::<Vec<_>>();
-        deck.shuffle(&mut rand::thread_rng());
+        // The deal rotates between players each game.
+        let first_bidder_index = game_index % rules.player_count;
 
         // Deal hands.
-        let chunks: Vec<&[Card]> = deck.chunks(10).collect();
-        debug_assert_eq!(chunks.len(), 5);
-        let hands: Vec<Vec<Card>> = chunks[0..4].iter().map(|h| h.to_vec()).collect();
-        let kitty = chunks[4].to_vec();
-        debug_assert_eq!(kitty.len(), 3);
+        let Deal { hands, kitty } = deck.deal();
 
+        let turn_deadline = super::turn_deadline(rules.bid_timeout_secs);
         let new = Bidding {
+            game_index,
+            rules,
+            deck,
             first_bidder_index,
             bids_made: 0,
             kitty,
-            prev_bids: vec![None; 4],
+            prev_bids: vec![None; rules.player_count],
             highest_bid: None,
+            passes_since_bid: 0,
+            turn_deadline: turn_deadline.map(|(instant, _)| instant),
         };
 
         for (index, (id, history)) in players.iter_mut().enumerate() {
-            // Clear old history and populate a new history with the new hand.
-            history.match_history = Some(api::MatchHistory {
-                past_games: Vec::new(),
-                winning_team_index: None,
-                match_aborted_reason: None,
-            });
+            // Clear old game history and populate a new history with the new hand.
             history.game_history = Some(api::GameHistory {
                 hand: hands[index].clone(),
                 bidding_history: Some(api::BiddingHistory {
-                    bids: vec![None; 4],
+                    bids: vec![None; rules.player_count],
                     current_bidder_index: first_bidder_index,
                     bid_options: None,
+                    all_passed: None,
                 }),
                 winning_bid_history: None,
                 plays_history: None,
+                turn_deadline: turn_deadline.map(|(_, ms)| ms),
             });
 
             // Send off hands to players.
@@ -103,28 +99,39 @@ impl Bidding {
                 },
             );
         }
+        clients.send_spectators(players, api::CurrentState::HandDealt);
+        clients.send_spectators(players, api::CurrentState::WaitingForTheirBid);
 
         new
     }
 }
 
 impl Bidding {
+    // The index of the player whose turn it is to bid.
+    fn current_bidder_index(&self) -> usize {
+        (self.first_bidder_index + self.bids_made) % self.rules.player_count
+    }
+
     // Returns the bids that the given player can take at this point in the
     // bidding (i.e. applying mis rules).
     fn available_bids(&self, player_index: usize) -> Vec<Bid> {
-        debug_assert!(player_index < 4);
+        debug_assert!(player_index < self.rules.player_count);
 
-        // Can't bid again if you've passed.
+        // Can't bid again if you've passed, unless the rules allow it.
         let mut bids: Vec<Bid> = vec![Bid::Pass];
-        if self.prev_bids[player_index] == Some(Bid::Pass) {
+        if self.prev_bids[player_index] == Some(Bid::Pass) && !self.rules.passed_players_reenter {
             return bids;
         }
 
-        // Can't bid miseres until everyone has had a chance to bid.
-        let can_bid_mis = self.prev_bids.iter().flatten().count() == 4;
+        // Miseres may only be available once everyone has had a chance to bid.
+        let can_bid_mis = match self.rules.misere_rule {
+            MisereRule::Disabled => false,
+            MisereRule::AfterAllBid => self.prev_bids.iter().all(|b| b.is_some()),
+            MisereRule::Anytime => true,
+        };
 
         let mut cur_bid = self.highest_bid.unwrap_or(Bid::Pass);
-        while let Some(bid) = next_bid(cur_bid) {
+        while let Some(bid) = next_bid(cur_bid, self.rules.misere_rank) {
             match bid {
                 Bid::Mis | Bid::OpenMis if can_bid_mis => {
                     bids.push(bid);
@@ -152,12 +159,14 @@ impl Stage for Bidding {
         step: &api::Step,
     ) -> Box<dyn Stage> {
         // Bail with an error response if this isn't a player.
-        let Some(index) = super::reject_nonplayer(player_index, clients, client_id, step) else { return self; };
+        let Some(index) = super::reject_nonplayer(player_index, clients, client_id, step) else {
+            return self;
+        };
 
         match step {
             api::Step::MakeBid(bid) => {
                 // Player is trying to bid out of turn.
-                if index != (self.first_bidder_index + self.bids_made) % 4 {
+                if index != self.current_bidder_index() {
                     error!("[client {}] tried to bid out of turn", client_id);
                     clients.send_event(
                         client_id,
@@ -194,6 +203,9 @@ impl Stage for Bidding {
                 self.prev_bids[index] = Some(*bid);
                 if *bid != Bid::Pass {
                     self.highest_bid = Some(*bid);
+                    self.passes_since_bid = 0;
+                } else {
+                    self.passes_since_bid += 1;
                 }
                 self.bids_made += 1;
 
@@ -205,9 +217,10 @@ impl Stage for Bidding {
                     unwrap_bidding_history(history).bids[index] = Some(*bid);
                     clients.send_event(id, history.clone(), api::CurrentState::PlayerBid);
                 }
+                clients.send_spectators(players, api::CurrentState::PlayerBid);
 
                 // If this is the last bid, it could transition us into the "bid won" stage.
-                let all_bid = self.prev_bids.iter().flatten().count() == 4;
+                let all_bid = self.prev_bids.iter().all(|b| b.is_some());
                 let pass_count = self
                     .prev_bids
                     .iter()
@@ -215,34 +228,77 @@ impl Stage for Bidding {
                     .count();
 
                 // All players passed without bidding!
-                if pass_count == 4 {
-                    // TODO: handle this.
-                    return self;
-                }
-
-                // The last bid has been made.
-                if self.highest_bid == Some(Bid::OpenMis) || (all_bid && pass_count == 3) {
-                    // Clear extraneous bidding history.
-                    for (_, history) in players.iter_mut() {
-                        history.game_history.as_mut().unwrap().bidding_history = None;
+                if pass_count == self.rules.player_count {
+                    info!("All players passed: {:?}.", self.rules.all_pass_rule);
+                    for (id, history) in players.iter_mut() {
+                        let bid_history = unwrap_bidding_history(history);
+                        bid_history.bid_options = None;
+                        bid_history.all_passed = Some(self.rules.all_pass_rule);
+                        clients.send_event(id, history.clone(), api::CurrentState::AllPassed);
                     }
+                    clients.send_spectators(players, api::CurrentState::AllPassed);
+
+                    return match self.rules.all_pass_rule {
+                        AllPassRule::Redeal => Box::new(Bidding::new(
+                            players,
+                            clients,
+                            self.game_index + 1,
+                            self.rules,
+                            self.deck,
+                        )),
+
+                        // The first bidder leads, and the kitty is left unused.
+                        AllPassRule::PlayNoTrumps => Box::new(Playing::new(
+                            players,
+                            clients,
+                            self.game_index,
+                            self.rules,
+                            self.deck,
+                        )),
+                    };
+                }
 
+                // The last bid has been made. If passed players can re-enter the bidding, every
+                // other player must have passed since the highest bid.
+                let bids_closed = if self.rules.passed_players_reenter {
+                    self.passes_since_bid == self.rules.player_count - 1
+                } else {
+                    pass_count == self.rules.player_count - 1
+                };
+                if self.highest_bid == Some(Bid::OpenMis) || (all_bid && bids_closed) {
                     let winner_index = self
                         .prev_bids
                         .iter()
                         .position(|&b| b == self.highest_bid)
                         .unwrap();
-                    return Box::new(bid_won::BidWon::new(
+
+                    // Replace extraneous bidding history with the winning bid.
+                    for (_, history) in players.iter_mut() {
+                        let game_history = history.game_history.as_mut().unwrap();
+                        game_history.bidding_history = None;
+                        game_history.winning_bid_history = Some(api::WinningBidHistory {
+                            winning_bidder_index: winner_index,
+                            winning_bid: self.highest_bid.unwrap(),
+                            bids: self.prev_bids.clone(),
+                            kitty: None,
+                            discarded: None,
+                            called_card: None,
+                        });
+                    }
+
+                    return Box::new(BidWon::new(
                         players,
                         clients,
-                        winner_index,
-                        self.highest_bid.unwrap(),
+                        self.game_index,
+                        self.rules,
+                        self.deck,
                         self.kitty,
                     ));
                 }
 
                 // Bidding is ongoing; broadcast the next bidder.
-                let new_bidder_index = (self.first_bidder_index + self.bids_made) % 4;
+                let new_bidder_index = self.current_bidder_index();
+                self.turn_deadline = super::start_turn(players, self.rules.bid_timeout_secs);
                 for (j, (id, history)) in players.iter_mut().enumerate() {
                     let bid_history = unwrap_bidding_history(history);
 
@@ -263,6 +319,7 @@ impl Stage for Bidding {
                         },
                     );
                 }
+                clients.send_spectators(players, api::CurrentState::WaitingForTheirBid);
             }
 
             _bad_step => {
@@ -272,11 +329,7 @@ impl Stage for Bidding {
                     clients,
                     client_id,
                     step,
-                    if index == (self.first_bidder_index + self.bids_made) % 4 {
-                        api::CurrentState::WaitingForYourBid
-                    } else {
-                        api::CurrentState::WaitingForTheirBid
-                    },
+                    self.waiting_state(index),
                     "during bidding",
                 );
             }
@@ -284,43 +337,71 @@ impl Stage for Bidding {
 
         self
     }
+
+    fn waiting_state(&self, player_index: usize) -> api::CurrentState {
+        if player_index == self.current_bidder_index() {
+            api::CurrentState::WaitingForYourBid
+        } else {
+            api::CurrentState::WaitingForTheirBid
+        }
+    }
+
+    fn turn_deadline(&self) -> Option<time::Instant> {
+        self.turn_deadline
+    }
+
+    // A bidder who runs out of time passes.
+    fn default_step(
+        &self,
+        _players: &[(events::ClientId, api::History)],
+    ) -> Option<(usize, api::Step)> {
+        Some((self.current_bidder_index(), api::Step::MakeBid(Bid::Pass)))
+    }
 }
 
-// Returns the next highest bid.
-fn next_bid(bid: Bid) -> Option<Bid> {
+// Returns the next highest bid, with misere placed among the eight-trick bids by the given rank.
+fn next_bid(bid: Bid, misere_rank: MisereRank) -> Option<Bid> {
+    // Mis is worth 250 pts. The Avondale schedule places it after eight spades (240 pts).
+    let mis_follows = match misere_rank {
+        MisereRank::AfterEightSpades => BidSuit::Suit(Suit::Spades),
+        MisereRank::AfterEightClubs => BidSuit::Suit(Suit::Clubs),
+    };
+
     match bid {
         Bid::Pass => Some(Bid::Tricks(6, BidSuit::Suit(Suit::Spades))),
 
-        // Mis is worth 270 pts.
-        Bid::Tricks(8, BidSuit::Suit(Suit::Clubs)) => Some(Bid::Mis),
-        Bid::Mis => Some(Bid::Tricks(8, BidSuit::Suit(Suit::Diamonds))),
-
-        Bid::Tricks(count, suit) => {
-            let new_count = if suit == BidSuit::NoTrumps {
-                count + 1
-            } else {
-                count
-            };
-            if new_count == 11 {
-                return Some(Bid::OpenMis);
-            }
-
-            let new_suit = match suit {
-                BidSuit::Suit(Suit::Spades) => BidSuit::Suit(Suit::Clubs),
-                BidSuit::Suit(Suit::Clubs) => BidSuit::Suit(Suit::Diamonds),
-                BidSuit::Suit(Suit::Diamonds) => BidSuit::Suit(Suit::Hearts),
-                BidSuit::Suit(Suit::Hearts) => BidSuit::NoTrumps,
-                BidSuit::NoTrumps => BidSuit::Suit(Suit::Spades),
-            };
+        Bid::Tricks(8, suit) if suit == mis_follows => Some(Bid::Mis),
+        Bid::Mis => Some(next_tricks_bid(8, mis_follows)),
 
-            Some(Bid::Tricks(new_count, new_suit))
-        }
+        Bid::Tricks(count, suit) => Some(next_tricks_bid(count, suit)),
 
         // No way to outbid open mis.
         Bid::OpenMis => None,
     }
 }
 
+// Returns the trick bid after the given one, or open mis after ten no trumps.
+fn next_tricks_bid(count: usize, suit: BidSuit) -> Bid {
+    let new_count = if suit == BidSuit::NoTrumps {
+        count + 1
+    } else {
+        count
+    };
+    if new_count == 11 {
+        return Bid::OpenMis;
+    }
+
+    let new_suit = match suit {
+        BidSuit::Suit(Suit::Spades) => BidSuit::Suit(Suit::Clubs),
+        BidSuit::Suit(Suit::Clubs) => BidSuit::Suit(Suit::Diamonds),
+        BidSuit::Suit(Suit::Diamonds) => BidSuit::Suit(Suit::Hearts),
+        BidSuit::Suit(Suit::Hearts) => BidSuit::NoTrumps,
+        BidSuit::NoTrumps => BidSuit::Suit(Suit::Spades),
+    };
+
+    Bid::Tricks(new_count, new_suit)
+}
+
 // Convenience function to extract a mutable bidding history from a full history.
 fn unwrap_bidding_history(history: &mut api::History) -> &mut api::BiddingHistory {
     history
@@ -331,3 +412,44 @@ fn unwrap_bidding_history(history: &mut api::History) -> &mut api::BiddingHistor
         .as_mut()
         .unwrap()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scoring;
+
+    // Under the default rules, open misere is the highest bid and every other bid is worth more
+    // than the last.
+    #[test]
+    fn next_bid_is_worth_more() {
+        let rank = Rules::default().misere_rank;
+        let mut bid = Bid::Tricks(6, BidSuit::Suit(Suit::Spades));
+        while let Some(next) = next_bid(bid, rank).filter(|&b| b != Bid::OpenMis) {
+            assert!(
+                scoring::bid_value(next) > scoring::bid_value(bid),
+                "{:?}",
+                next
+            );
+            bid = next;
+        }
+        assert_eq!(bid, Bid::Tricks(10, BidSuit::NoTrumps));
+        assert_eq!(next_bid(bid, rank), Some(Bid::OpenMis));
+    }
+
+    #[test]
+    fn misere_sits_where_the_rules_rank_it() {
+        let tricks = |suit| Bid::Tricks(8, BidSuit::Suit(suit));
+
+        let rank = MisereRank::AfterEightSpades;
+        assert_eq!(next_bid(tricks(Suit::Spades), rank), Some(Bid::Mis));
+        assert_eq!(next_bid(Bid::Mis, rank), Some(tricks(Suit::Clubs)));
+
+        let rank = MisereRank::AfterEightClubs;
+        assert_eq!(
+            next_bid(tricks(Suit::Spades), rank),
+            Some(tricks(Suit::Clubs))
+        );
+        assert_eq!(next_bid(tricks(Suit::Clubs), rank), Some(Bid::Mis));
+        assert_eq!(next_bid(Bid::Mis, rank), Some(tricks(Suit::Diamonds)));
+    }
+}
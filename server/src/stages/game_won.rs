@@ -0,0 +1,158 @@
+// The transition at the end of a game, after the last trick has been played. The game is scored,
+// and then either the match is won or the next game is dealt.
+
+use crate::api;
+use crate::events;
+use crate::scoring;
+use crate::types::*;
+
+use log::info;
+
+use super::Bidding;
+use super::MatchWon;
+use super::Stage;
+
+// Scores the finished game and returns the next stage of the session.
+pub fn end_game(
+    players: &mut [(events::ClientId, api::History)],
+    clients: &events::ClientMap,
+    game_index: usize,
+    player_tricks: &[usize],
+    partner_index: Option<usize>,
+    rules: Rules,
+    deck: Deck,
+) -> Box<dyn Stage> {
+    let team_count = rules.team_count();
+    let player_teams = players
+        .iter()
+        .map(|(_, history)| history.lobby_history.as_ref().unwrap().your_team_index)
+        .collect::<Vec<_>>();
+    let tricks_won = (0..team_count)
+        .map(|team| scoring::team_tricks(team, &player_teams, player_tricks))
+        .collect::<Vec<_>>();
+
+    // Invariant: all players share the same game history. There is no winning bid if the hand was
+    // played after every player passed.
+    let winning_bid_history = players[0]
+        .1
+        .game_history
+        .as_ref()
+        .unwrap()
+        .winning_bid_history
+        .clone();
+
+    let (winning_team_index, contract_teams, slam, score_deltas) = match &winning_bid_history {
+        Some(h) => {
+            let bidding_teams =
+                scoring::bidding_teams(h.winning_bidder_index, partner_index, &player_teams);
+            let made = scoring::contract_made(
+                h.winning_bid,
+                h.winning_bidder_index,
+                partner_index,
+                &player_teams,
+                player_tricks,
+            );
+            let slam = rules.slam_bonus
+                && scoring::slam_made(
+                    h.winning_bid,
+                    h.winning_bidder_index,
+                    partner_index,
+                    &player_teams,
+                    player_tricks,
+                );
+
+            // A defeated contract is only won by the defenders if they are a single team.
+            let defending_teams = (0..team_count)
+                .filter(|t| !bidding_teams.contains(t))
+                .collect::<Vec<_>>();
+
+            (
+                if made {
+                    Some(player_teams[h.winning_bidder_index])
+                } else if defending_teams.len() == 1 {
+                    Some(defending_teams[0])
+                } else {
+                    None
+                },
+                if made { bidding_teams } else { Vec::new() },
+                slam,
+                scoring::score_deltas(
+                    h.winning_bid,
+                    h.winning_bidder_index,
+                    partner_index,
+                    &player_teams,
+                    player_tricks,
+                    team_count,
+                    rules.slam_bonus,
+                ),
+            )
+        }
+
+        // Without a contract, the team with the most tricks wins (if there is only one).
+        None => {
+            let most_tricks = *tricks_won.iter().max().unwrap();
+            let most_tricks_teams = (0..team_count)
+                .filter(|&t| tricks_won[t] == most_tricks)
+                .collect::<Vec<_>>();
+
+            (
+                (most_tricks_teams.len() == 1).then(|| most_tricks_teams[0]),
+                Vec::new(),
+                false,
+                scoring::no_contract_score_deltas(&player_teams, player_tricks, team_count),
+            )
+        }
+    };
+
+    // Invariant: all players share the same match history.
+    let prev_totals = unwrap_match_history(&mut players[0].1)
+        .past_games
+        .last()
+        .map(|r| r.score_totals.clone())
+        .unwrap_or(vec![0; team_count]);
+    let score_totals = prev_totals
+        .iter()
+        .zip(&score_deltas)
+        .map(|(total, delta)| total + delta)
+        .collect::<Vec<_>>();
+
+    let result = api::GameResult {
+        winning_bidder_index: winning_bid_history.as_ref().map(|h| h.winning_bidder_index),
+        winning_bid: winning_bid_history
+            .as_ref()
+            .map(|h| h.winning_bid)
+            .unwrap_or(Bid::Pass),
+        partner_index,
+        tricks_won,
+        winning_team_index,
+        slam,
+        score_deltas,
+        score_totals: score_totals.clone(),
+    };
+    info!("Game finished: {:?}", result);
+
+    // Notify players of the winner, and then of the new scores.
+    for (id, history) in players.iter_mut() {
+        unwrap_match_history(history)
+            .past_games
+            .push(result.clone());
+        clients.send_event(id, history.clone(), api::CurrentState::GameWon);
+    }
+    clients.send_spectators(players, api::CurrentState::GameWon);
+
+    for (id, history) in players.iter() {
+        clients.send_event(id, history.clone(), api::CurrentState::ScoresUpdated);
+    }
+    clients.send_spectators(players, api::CurrentState::ScoresUpdated);
+
+    // Either the match is over, or we deal the next game.
+    match scoring::match_winner(&score_totals, &contract_teams, rules.target_score) {
+        Some(winning_team_index) => Box::new(MatchWon::new(players, clients, winning_team_index)),
+        None => Box::new(Bidding::new(players, clients, game_index + 1, rules, deck)),
+    }
+}
+
+// Convenience function to extract a mutable match history from a full history.
+fn unwrap_match_history(history: &mut api::History) -> &mut api::MatchHistory {
+    history.match_history.as_mut().unwrap()
+}
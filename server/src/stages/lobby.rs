@@ -1,26 +1,55 @@
-// The stage of the session where players are waiting to join a new game.
+// The stage of the session where players are waiting to join a new game. Players can leave, change
+// teams and swap seats until the table is full and every player has said they are ready. Two
+// players only swap seats once both have asked to.
 
-use super::bidding;
+use super::Bidding;
 use super::Stage;
 
 use crate::api;
 use crate::events;
+use crate::types::*;
 
 use log::info;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use std::collections::HashMap;
+
+// The number of characters in a resume token.
+const RESUME_TOKEN_LENGTH: usize = 16;
+
+// The maximum number of characters in a display name.
+const MAX_NAME_LENGTH: usize = 20;
 
 pub struct Lobby {
     game_index: usize,
+    rules: Rules,
+    deck: Deck,
+
+    // The player each player has asked to swap seats with, until the other player agrees.
+    swap_requests: HashMap<events::ClientId, events::ClientId>,
+}
+
+impl Lobby {
+    pub fn new(game_index: usize, rules: Rules, deck: Deck) -> Self {
+        Lobby {
+            game_index,
+            rules,
+            deck,
+            swap_requests: HashMap::new(),
+        }
+    }
 }
 
 impl Lobby {
-    pub fn new(game_index: usize) -> Self {
-        Lobby { game_index }
+    // Returns the number of seats on each team.
+    fn team_capacity(&self) -> usize {
+        self.rules.player_count / self.rules.team_count()
     }
 }
 
 impl Stage for Lobby {
     fn process_step(
-        self: Box<Self>,
+        mut self: Box<Self>,
         players: &mut Vec<(events::ClientId, api::History)>,
         player_index: Option<usize>,
         clients: &events::ClientMap,
@@ -29,7 +58,7 @@ impl Stage for Lobby {
     ) -> Box<dyn Stage> {
         match &step {
             // A client is attempting to join.
-            api::Step::Join(_) => {
+            api::Step::Join(team_index, name) => {
                 // Client is already in the player list.
                 if let Some(i) = player_index {
                     clients.send_event(
@@ -47,15 +76,79 @@ impl Stage for Lobby {
                     return self;
                 }
 
-                // Note: starts with incorrect player count to match the other histories that are
-                // now out-of-date.
+                // The requested team must exist and have a seat free.
+                let team_error = if *team_index >= self.rules.team_count() {
+                    Some("There's no such team.")
+                } else if team_size(players, *team_index) == self.team_capacity() {
+                    Some("That team is full.")
+                } else {
+                    None
+                };
+
+                if let Some(reason) = team_error {
+                    clients.send_event(
+                        client_id,
+                        api::History {
+                            excluded_reason: Some(reason.to_string()),
+                            ..Default::default()
+                        },
+                        api::CurrentState::Excluded,
+                    );
+                    info!(
+                        "[client {}] excluded from team {}: {}",
+                        client_id, team_index, reason
+                    );
+                    return self;
+                }
+
+                let (mut player_names, mut players_ready) = shared_lobby(players);
+
+                // Names must be non-empty, short enough to display, and distinguishable from the
+                // other players' names.
+                let name = name.trim();
+                let name_error = if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+                    Some(format!(
+                        "Names must be between 1 and {} characters.",
+                        MAX_NAME_LENGTH
+                    ))
+                } else if player_names
+                    .iter()
+                    .any(|n| n.to_lowercase() == name.to_lowercase())
+                {
+                    Some("That name is taken.".to_string())
+                } else {
+                    None
+                };
+
+                if let Some(reason) = name_error {
+                    clients.send_event(
+                        client_id,
+                        api::History {
+                            excluded_reason: Some(reason.clone()),
+                            ..Default::default()
+                        },
+                        api::CurrentState::Excluded,
+                    );
+                    info!(
+                        "[client {}] excluded because of their name: {}",
+                        client_id, reason
+                    );
+                    return self;
+                }
+                player_names.push(name.to_string());
+                players_ready.push(false);
+
                 players.push((
                     (*client_id.clone()).to_string(),
                     api::History {
                         lobby_history: Some(api::LobbyHistory {
-                            player_count: players.len(),
-                            your_player_index: players.len(),
-                            your_team_index: players.len() % 2,
+                            your_team_index: *team_index,
+                            resume_token: rand::thread_rng()
+                                .sample_iter(&Alphanumeric)
+                                .take(RESUME_TOKEN_LENGTH)
+                                .map(char::from)
+                                .collect(),
+                            ..Default::default()
                         }),
                         match_history: Some(api::MatchHistory {
                             ..Default::default()
@@ -63,21 +156,218 @@ impl Stage for Lobby {
                         ..Default::default()
                     },
                 ));
-                info!("[client {}] joined.", client_id);
+                info!("[client {}] joined as {}.", client_id, name);
 
                 // Let players know another has joined.
-                for (id, history) in &mut *players {
-                    // Invariant: all instances added to the players list have
-                    // lobby history populated.
-                    history.lobby_history.as_mut().unwrap().player_count += 1;
-                    clients.send_event(id, history.clone(), api::CurrentState::PlayerJoined);
-                }
+                update_lobby(
+                    players,
+                    clients,
+                    player_names,
+                    players_ready,
+                    &self.swap_requests,
+                    api::CurrentState::PlayerJoined,
+                );
+            }
+
+            // A player is saying whether they are ready for the deal.
+            api::Step::Ready(ready) if player_index.is_some() => {
+                let index = player_index.unwrap();
+                let (player_names, mut players_ready) = shared_lobby(players);
+                players_ready[index] = *ready;
+                info!("[client {}] ready: {}.", client_id, ready);
+
+                update_lobby(
+                    players,
+                    clients,
+                    player_names,
+                    players_ready.clone(),
+                    &self.swap_requests,
+                    api::CurrentState::LobbyUpdated,
+                );
 
-                // All players newly joined.
-                if players.len() == 4 {
+                // The table is full and everyone is ready.
+                if players.len() == self.rules.player_count && players_ready.iter().all(|&r| r) {
                     info!("Starting match.");
-                    return Box::new(bidding::Bidding::new(players, clients, self.game_index % 4));
+                    seat_players(players, self.rules);
+                    return Box::new(Bidding::new(
+                        players,
+                        clients,
+                        self.game_index,
+                        self.rules,
+                        self.deck,
+                    ));
+                }
+            }
+
+            // A player is moving to a different team.
+            api::Step::ChangeTeam(team_index) if player_index.is_some() => {
+                let index = player_index.unwrap();
+
+                // The requested team must exist, differ from the player's team, and have a seat
+                // free.
+                let current_team_index = players[index]
+                    .1
+                    .lobby_history
+                    .as_ref()
+                    .unwrap()
+                    .your_team_index;
+                let team_error = if *team_index >= self.rules.team_count() {
+                    Some("There's no such team.")
+                } else if *team_index == current_team_index {
+                    Some("You're already on that team.")
+                } else if team_size(players, *team_index) == self.team_capacity() {
+                    Some("That team is full.")
+                } else {
+                    None
+                };
+
+                if let Some(error) = team_error {
+                    clients.send_event(
+                        client_id,
+                        api::History {
+                            error: Some(error.to_string()),
+                            ..players[index].1.clone()
+                        },
+                        api::CurrentState::PlayerJoined,
+                    );
+                    info!(
+                        "[client {}] couldn't move to team {}: {}",
+                        client_id, team_index, error
+                    );
+                    return self;
                 }
+
+                // Changing seats means the player must ready up again.
+                let (player_names, mut players_ready) = shared_lobby(players);
+                players_ready[index] = false;
+                players[index]
+                    .1
+                    .lobby_history
+                    .as_mut()
+                    .unwrap()
+                    .your_team_index = *team_index;
+                info!("[client {}] moved to team {}.", client_id, team_index);
+
+                update_lobby(
+                    players,
+                    clients,
+                    player_names,
+                    players_ready,
+                    &self.swap_requests,
+                    api::CurrentState::LobbyUpdated,
+                );
+            }
+
+            // A player is swapping seats (and so possibly teams) with another player.
+            api::Step::SwapSeats(other_index) if player_index.is_some() => {
+                let index = player_index.unwrap();
+
+                if *other_index >= players.len() || *other_index == index {
+                    clients.send_event(
+                        client_id,
+                        api::History {
+                            error: Some("There's no other player in that seat.".to_string()),
+                            ..players[index].1.clone()
+                        },
+                        api::CurrentState::PlayerJoined,
+                    );
+                    info!("[client {}] couldn't swap seats.", client_id);
+                    return self;
+                }
+
+                // The other player must agree to the swap by asking to swap with this player.
+                let other_id = players[*other_index].0.clone();
+                if self.swap_requests.get(&other_id) != Some(client_id) {
+                    self.swap_requests
+                        .insert(client_id.clone(), other_id.clone());
+                    info!(
+                        "[client {}] asked to swap seats with [client {}].",
+                        client_id, other_id
+                    );
+
+                    let (player_names, players_ready) = shared_lobby(players);
+                    update_lobby(
+                        players,
+                        clients,
+                        player_names,
+                        players_ready,
+                        &self.swap_requests,
+                        api::CurrentState::LobbyUpdated,
+                    );
+                    return self;
+                }
+                self.swap_requests.remove(client_id);
+                self.swap_requests.remove(&other_id);
+
+                // Each player takes the other's place, including their team. Both players must
+                // ready up again.
+                let (mut player_names, mut players_ready) = shared_lobby(players);
+                player_names.swap(index, *other_index);
+                players_ready[index] = false;
+                players_ready[*other_index] = false;
+
+                let team_index = |history: &api::History| {
+                    history.lobby_history.as_ref().unwrap().your_team_index
+                };
+                let (team, other_team) = (
+                    team_index(&players[index].1),
+                    team_index(&players[*other_index].1),
+                );
+                players[index]
+                    .1
+                    .lobby_history
+                    .as_mut()
+                    .unwrap()
+                    .your_team_index = other_team;
+                players[*other_index]
+                    .1
+                    .lobby_history
+                    .as_mut()
+                    .unwrap()
+                    .your_team_index = team;
+                players.swap(index, *other_index);
+                info!(
+                    "[client {}] swapped seats with [client {}].",
+                    client_id, players[index].0
+                );
+
+                update_lobby(
+                    players,
+                    clients,
+                    player_names,
+                    players_ready,
+                    &self.swap_requests,
+                    api::CurrentState::LobbyUpdated,
+                );
+            }
+
+            // A player is leaving the lobby. Their seat is freed up for someone else.
+            api::Step::Quit if player_index.is_some() => {
+                let index = player_index.unwrap();
+                let (mut player_names, mut players_ready) = shared_lobby(players);
+                player_names.remove(index);
+                players_ready.remove(index);
+                players.remove(index);
+                self.swap_requests
+                    .retain(|from, to| from != client_id && to != client_id);
+                info!("[client {}] left the lobby.", client_id);
+
+                clients.send_event(
+                    client_id,
+                    api::History {
+                        excluded_reason: Some("You left the lobby.".to_string()),
+                        ..Default::default()
+                    },
+                    api::CurrentState::Excluded,
+                );
+                update_lobby(
+                    players,
+                    clients,
+                    player_names,
+                    players_ready,
+                    &self.swap_requests,
+                    api::CurrentState::LobbyUpdated,
+                );
             }
 
             // A client has made a step that isn't valid in the lobby.
@@ -100,4 +390,105 @@ impl Stage for Lobby {
 
         self
     }
+
+    fn waiting_state(&self, _player_index: usize) -> api::CurrentState {
+        api::CurrentState::PlayerJoined
+    }
+
+    fn match_started(&self) -> bool {
+        false
+    }
+}
+
+// Returns the names and ready statuses of the joined players, which all players share.
+fn shared_lobby(players: &[(events::ClientId, api::History)]) -> (Vec<String>, Vec<bool>) {
+    // Invariant: all instances added to the players list have lobby history populated.
+    players
+        .first()
+        .map(|(_, history)| {
+            let lobby_history = history.lobby_history.as_ref().unwrap();
+            (
+                lobby_history.player_names.clone(),
+                lobby_history.players_ready.clone(),
+            )
+        })
+        .unwrap_or_default()
+}
+
+// Updates every player's lobby history after the player list has changed, and notifies them with
+// the given state.
+fn update_lobby(
+    players: &mut [(events::ClientId, api::History)],
+    clients: &events::ClientMap,
+    player_names: Vec<String>,
+    players_ready: Vec<bool>,
+    swap_requests: &HashMap<events::ClientId, events::ClientId>,
+    state: api::CurrentState,
+) {
+    let player_count = players.len();
+    let ids = players.iter().map(|(id, _)| id.clone()).collect::<Vec<_>>();
+    for (i, (id, history)) in players.iter_mut().enumerate() {
+        let lobby_history = history.lobby_history.as_mut().unwrap();
+        lobby_history.player_count = player_count;
+        lobby_history.your_player_index = i;
+        lobby_history.player_names = player_names.clone();
+        lobby_history.players_ready = players_ready.clone();
+        lobby_history.swap_requests = (0..player_count)
+            .filter(|&j| swap_requests.get(&ids[j]) == Some(id))
+            .collect();
+        clients.send_event(id, history.clone(), state.clone());
+    }
+    clients.send_spectators(players, state);
+}
+
+// Returns the number of players who have joined the given team.
+fn team_size(players: &[(events::ClientId, api::History)], team_index: usize) -> usize {
+    players
+        .iter()
+        .filter(|(_, history)| {
+            history.lobby_history.as_ref().unwrap().your_team_index == team_index
+        })
+        .count()
+}
+
+// Reorders a full table so that teams alternate around it. Within each team, players keep the order
+// in which they joined.
+fn seat_players(players: &mut Vec<(events::ClientId, api::History)>, rules: Rules) {
+    // The join-order index of the player in each seat.
+    let order = (0..players.len())
+        .map(|seat| {
+            players
+                .iter()
+                .enumerate()
+                .filter(|(_, (_, history))| {
+                    history.lobby_history.as_ref().unwrap().your_team_index
+                        == rules.team_index(seat)
+                })
+                .nth(seat / rules.team_count())
+                .unwrap()
+                .0
+        })
+        .collect::<Vec<_>>();
+    let seat_of = |i: usize| order.iter().position(|&j| j == i).unwrap();
+
+    // Invariant: all players share the same names and disconnections, listed in join order.
+    let old_lobby_history = players[0].1.lobby_history.clone().unwrap();
+    let player_names = order
+        .iter()
+        .map(|&i| old_lobby_history.player_names[i].clone())
+        .collect::<Vec<_>>();
+    let disconnected_player_indices = old_lobby_history
+        .disconnected_player_indices
+        .iter()
+        .map(|&i| seat_of(i))
+        .collect::<Vec<_>>();
+
+    *players = order.iter().map(|&i| players[i].clone()).collect();
+    for (seat, (_, history)) in players.iter_mut().enumerate() {
+        let lobby_history = history.lobby_history.as_mut().unwrap();
+        lobby_history.your_player_index = seat;
+        lobby_history.player_names = player_names.clone();
+        lobby_history.disconnected_player_indices = disconnected_player_indices.clone();
+        lobby_history.swap_requests = Vec::new();
+    }
 }
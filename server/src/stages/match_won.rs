@@ -0,0 +1,60 @@
+// The stage of the session after a team has won the match.
+
+use crate::api;
+use crate::events;
+
+use log::info;
+
+pub struct MatchWon {}
+
+impl MatchWon {
+    pub fn new(
+        players: &mut [(events::ClientId, api::History)],
+        clients: &events::ClientMap,
+        winning_team_index: usize,
+    ) -> Self {
+        info!("Team {} won the match.", winning_team_index);
+
+        for (id, history) in players.iter_mut() {
+            history.match_history.as_mut().unwrap().winning_team_index = Some(winning_team_index);
+            clients.send_event(id, history.clone(), api::CurrentState::MatchWon);
+        }
+        clients.send_spectators(players, api::CurrentState::MatchWon);
+
+        MatchWon {}
+    }
+}
+
+impl super::Stage for MatchWon {
+    // Always send the final state back.
+    fn process_step(
+        self: Box<Self>,
+        players: &mut Vec<(events::ClientId, api::History)>,
+        player_index: Option<usize>,
+        clients: &events::ClientMap,
+        client_id: &events::ClientId,
+        _step: &api::Step,
+    ) -> Box<dyn super::Stage> {
+        // Include player history if this client is a valid player.
+        let history = if let Some(i) = player_index {
+            players[i].1.clone()
+        } else {
+            api::History {
+                error: Some("Match is over.".to_string()),
+                ..Default::default()
+            }
+        };
+
+        clients.send_event(client_id, history, api::CurrentState::MatchWon);
+
+        self
+    }
+
+    fn waiting_state(&self, _player_index: usize) -> api::CurrentState {
+        api::CurrentState::MatchWon
+    }
+
+    fn match_over(&self) -> bool {
+        true
+    }
+}
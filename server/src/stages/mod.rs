@@ -1,21 +1,28 @@
 mod aborted;
 mod bid_won;
 mod bidding;
+mod game_won;
 mod lobby;
+mod match_won;
+mod playing;
 
 pub use self::aborted::Aborted;
 pub use self::bid_won::BidWon;
 pub use self::bidding::Bidding;
 pub use self::lobby::Lobby;
+pub use self::match_won::MatchWon;
+pub use self::playing::Playing;
 
 use crate::api;
 use crate::events;
 
 use log::error;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time;
 
 // The trait that each major "stage" (e.g. bidding, playing tricks) of a session should implement
 // in order to be coordinated by the game engine.
-pub trait Stage {
+pub trait Stage: Send {
     // Accepts a step request from a client, and returns the next stage of the session. A stage
     // instance can return itself if the session stage hasn't changed.
     //
@@ -30,6 +37,63 @@ pub trait Stage {
         client_id: &events::ClientId,
         step: &api::Step,
     ) -> Box<dyn Stage>;
+
+    // Returns the state that the given player should be waiting in. Used to bring a player up to
+    // date when they resume their seat.
+    fn waiting_state(&self, player_index: usize) -> api::CurrentState;
+
+    // Returns whether the match has started, after which players can no longer leave without
+    // ending it.
+    fn match_started(&self) -> bool {
+        true
+    }
+
+    // Returns whether the match has finished, either won or aborted. Seats are no longer held for
+    // disconnected players once it has.
+    fn match_over(&self) -> bool {
+        false
+    }
+
+    // Returns when the current turn runs out, if it is timed.
+    fn turn_deadline(&self) -> Option<time::Instant> {
+        None
+    }
+
+    // Returns the player whose turn it is and the step to take for them once their turn runs out.
+    fn default_step(
+        &self,
+        _players: &[(events::ClientId, api::History)],
+    ) -> Option<(usize, api::Step)> {
+        None
+    }
+}
+
+// Returns the deadline for a new turn with the given time limit, both as an instant to wait for
+// and in milliseconds since the Unix epoch (for clients).
+fn turn_deadline(timeout_secs: Option<u64>) -> Option<(time::Instant, u64)> {
+    let timeout = Duration::from_secs(timeout_secs?);
+    let deadline_ms = (SystemTime::now() + timeout)
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64;
+
+    Some((time::Instant::now() + timeout, deadline_ms))
+}
+
+// Starts a new turn with the given time limit, recording its deadline in every player's game
+// history. Returns the deadline, if the turn is timed.
+fn start_turn(
+    players: &mut [(events::ClientId, api::History)],
+    timeout_secs: Option<u64>,
+) -> Option<time::Instant> {
+    let deadline = turn_deadline(timeout_secs);
+    for (_, history) in players.iter_mut() {
+        if let Some(game_history) = history.game_history.as_mut() {
+            game_history.turn_deadline = deadline.map(|(_, ms)| ms);
+        }
+    }
+
+    deadline.map(|(instant, _)| instant)
 }
 
 // Common logic to return an error response to a client that isn't a player.
@@ -43,7 +107,7 @@ fn reject_nonplayer(
         return player_index;
     }
 
-    if let api::Step::Join(_) = step {
+    if let api::Step::Join(..) = step {
         clients.send_event(
             client_id,
             api::History {
@@ -63,12 +127,12 @@ fn reject_nonplayer(
         );
     }
 
-    return None;
+    None
 }
 
 // Common logic to return an error message for an unexpected step.
 fn process_bad_step(
-    players: &mut Vec<(events::ClientId, api::History)>,
+    players: &mut [(events::ClientId, api::History)],
     player_index: Option<usize>,
     clients: &events::ClientMap,
     client_id: &events::ClientId,
@@ -87,7 +151,7 @@ fn process_bad_step(
             error: Some(format!("Invalid step {}", stage_name)),
             ..player_index
                 .map(|i| players[i].1.clone())
-                .unwrap_or(Default::default())
+                .unwrap_or_default()
         },
         state,
     );
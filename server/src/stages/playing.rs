@@ -0,0 +1,458 @@
+// The stage of the game where players play out the ten tricks of a hand.
+//
+// In misere, the bidder's partner sits out and the remaining three players play three-card tricks.
+// The hand ends early if the bidder wins a trick. In open misere, the bidder's hand is revealed to
+// the other players once the opening lead has been made.
+//
+// In five-handed play, the bidder's called partner is revealed to everyone when the called card is
+// played.
+//
+// Without trumps (i.e. in no trumps and miseres), the joker's holder nominates its suit when they
+// play it, and the suit is then shown to everyone.
+
+use crate::api;
+use crate::events;
+use crate::scoring;
+use crate::tricks;
+use crate::types::*;
+
+use log::{error, info};
+use tokio::time;
+
+use super::game_won;
+use super::Stage;
+
+pub struct Playing {
+    game_index: usize,
+    rules: Rules,
+    deck: Deck,
+
+    // The trump suit of the winning bid.
+    trumps: BidSuit,
+
+    // The bidder, if they have bid misere.
+    misere_bidder_index: Option<usize>,
+
+    // Whether the misere bid is open misere.
+    open_misere: bool,
+
+    // The player sitting out of the hand, if there is one.
+    inactive_player_index: Option<usize>,
+
+    // The winning bidder's called partner, if a card was called and another player holds it.
+    partner_index: Option<usize>,
+
+    // The card named to call the partner, if one was named.
+    called_card: Option<Card>,
+
+    // The player who led the ongoing trick.
+    leader_index: usize,
+
+    // The number of cards played to the ongoing trick.
+    plays_made: usize,
+
+    // The number of completed tricks.
+    tricks_played: usize,
+
+    // The ongoing trick. Ordered from player 1 to player N.
+    current_trick: Vec<Option<Play>>,
+
+    // The number of tricks won by each player.
+    player_tricks: Vec<usize>,
+
+    // When the current turn runs out, if it is timed.
+    turn_deadline: Option<time::Instant>,
+}
+
+impl Playing {
+    pub fn new(
+        players: &mut [(events::ClientId, api::History)],
+        clients: &events::ClientMap,
+        game_index: usize,
+        rules: Rules,
+        deck: Deck,
+    ) -> Self {
+        // Invariant: all players share the same game history. There is no winning bid if the hand
+        // is played after every player passed.
+        let winning_bid_history = unwrap_game_history(&mut players[0].1)
+            .winning_bid_history
+            .clone();
+
+        // The winning bidder leads. Without one, the first bidder leads instead.
+        let leader_index = winning_bid_history
+            .as_ref()
+            .map(|h| h.winning_bidder_index)
+            .unwrap_or(game_index % rules.player_count);
+        let trumps = winning_bid_history
+            .as_ref()
+            .map(|h| tricks::bid_trumps(h.winning_bid))
+            .unwrap_or(BidSuit::NoTrumps);
+
+        let misere_bidder_index = winning_bid_history
+            .as_ref()
+            .filter(|h| matches!(h.winning_bid, Bid::Mis | Bid::OpenMis))
+            .map(|h| h.winning_bidder_index);
+        let open_misere = winning_bid_history
+            .as_ref()
+            .is_some_and(|h| h.winning_bid == Bid::OpenMis);
+        // Without fixed teams, there is no partner to sit out.
+        let inactive_player_index = misere_bidder_index.and_then(|bidder_index| {
+            let bidder_team = team_index(&players[bidder_index].1);
+            (0..players.len())
+                .find(|&j| j != bidder_index && team_index(&players[j].1) == bidder_team)
+        });
+
+        // The bidder plays alone if they hold the called card themselves.
+        let called_card = winning_bid_history.as_ref().and_then(|h| h.called_card);
+        let partner_index = called_card
+            .and_then(|card| {
+                players
+                    .iter_mut()
+                    .position(|(_, history)| unwrap_game_history(history).hand.contains(&card))
+            })
+            .filter(|&j| j != leader_index);
+
+        let mut new = Playing {
+            game_index,
+            rules,
+            deck,
+            trumps,
+            misere_bidder_index,
+            open_misere,
+            inactive_player_index,
+            partner_index,
+            called_card,
+            leader_index,
+            plays_made: 0,
+            tricks_played: 0,
+            current_trick: vec![None; rules.player_count],
+            player_tricks: vec![0; rules.player_count],
+            turn_deadline: None,
+        };
+
+        // The sitting-out player has no cards in play.
+        let hand_sizes = players
+            .iter_mut()
+            .enumerate()
+            .map(|(j, (_, history))| {
+                if Some(j) == inactive_player_index {
+                    0
+                } else {
+                    unwrap_game_history(history).hand.len()
+                }
+            })
+            .collect::<Vec<_>>();
+
+        for (j, (_, history)) in players.iter_mut().enumerate() {
+            unwrap_game_history(history).plays_history = Some(api::PlaysHistory {
+                joker_suit: None,
+                your_tricks_count: 0,
+                their_tricks_count: 0,
+                hand_sizes: hand_sizes.clone(),
+                previous_trick: None,
+                previous_trick_winner: None,
+                played_tricks: Vec::new(),
+                current_trick: vec![None; rules.player_count],
+                currently_playing_player_index: leader_index,
+                play_options: None,
+                inactive_player_index,
+                open_hand: None,
+
+                // The partner knows who they are from the start.
+                partner_index: partner_index.filter(|&p| p == j),
+            });
+        }
+
+        // Notify players who leads the first trick.
+        new.update_play_options(players);
+        new.turn_deadline = super::start_turn(players, rules.play_timeout_secs);
+        for (i, (id, history)) in players.iter().enumerate() {
+            clients.send_event(id, history.clone(), new.waiting_state(i));
+        }
+        clients.send_spectators(players, new.waiting_state(0));
+
+        new
+    }
+}
+
+impl Playing {
+    // The index of the player whose turn it is to play.
+    fn current_player_index(&self) -> usize {
+        let mut index = self.leader_index;
+        for _ in 0..self.plays_made {
+            index = (index + 1) % self.rules.player_count;
+            if Some(index) == self.inactive_player_index {
+                index = (index + 1) % self.rules.player_count;
+            }
+        }
+
+        index
+    }
+
+    // The number of players who play to each trick.
+    fn active_player_count(&self) -> usize {
+        self.rules.player_count - self.inactive_player_index.iter().count()
+    }
+
+    // The suit led in the ongoing trick, if it has been led.
+    fn led_suit(&self) -> Option<Suit> {
+        self.current_trick[self.leader_index].map(|p| tricks::effective_suit(self.trumps, p))
+    }
+
+    // Populates the play options of the current player, and clears everyone else's.
+    fn update_play_options(&self, players: &mut [(events::ClientId, api::History)]) {
+        for (j, (_, history)) in players.iter_mut().enumerate() {
+            let game_history = unwrap_game_history(history);
+            let options = (j == self.current_player_index()).then(|| {
+                tricks::legal_plays(
+                    self.trumps,
+                    self.rules.no_trumps_joker_void_only,
+                    &game_history.hand,
+                    self.led_suit(),
+                )
+            });
+            game_history.plays_history.as_mut().unwrap().play_options = options;
+        }
+    }
+
+    // Reveals the open misere bidder's remaining hand to the other players.
+    fn update_open_hand(&self, players: &mut [(events::ClientId, api::History)]) {
+        let Some(bidder_index) = self.misere_bidder_index.filter(|_| self.open_misere) else {
+            return;
+        };
+
+        let hand = unwrap_game_history(&mut players[bidder_index].1)
+            .hand
+            .clone();
+        for (j, (_, history)) in players.iter_mut().enumerate() {
+            if j != bidder_index {
+                unwrap_plays_history(history).open_hand = Some(hand.clone());
+            }
+        }
+    }
+}
+
+impl Stage for Playing {
+    fn process_step(
+        mut self: Box<Self>,
+        players: &mut Vec<(events::ClientId, api::History)>,
+        player_index: Option<usize>,
+        clients: &events::ClientMap,
+        client_id: &events::ClientId,
+        step: &api::Step,
+    ) -> Box<dyn Stage> {
+        // Bail with an error response if this isn't a player.
+        let Some(index) = super::reject_nonplayer(player_index, clients, client_id, step) else {
+            return self;
+        };
+
+        match step {
+            api::Step::MakePlay(play) => {
+                // Player is trying to play out of turn.
+                if index != self.current_player_index() {
+                    error!("[client {}] tried to play out of turn", client_id);
+                    clients.send_event(
+                        client_id,
+                        api::History {
+                            error: Some("Not your turn to play.".to_string()),
+                            ..players[index].1.clone()
+                        },
+                        api::CurrentState::WaitingForTheirPlay,
+                    );
+
+                    return self;
+                }
+
+                // Player has made a play that isn't one of their options.
+                if !unwrap_plays_history(&mut players[index].1)
+                    .play_options
+                    .as_ref()
+                    .is_some_and(|options| options.contains(play))
+                {
+                    super::process_bad_step(
+                        players,
+                        player_index,
+                        clients,
+                        client_id,
+                        step,
+                        api::CurrentState::WaitingForYourPlay,
+                        "during play",
+                    );
+
+                    return self;
+                }
+
+                // Now we know the player is the current player and has made a valid play.
+
+                // Update our internal state and the player's hand.
+                let hand = &mut unwrap_game_history(&mut players[index].1).hand;
+                let card_index = hand
+                    .iter()
+                    .position(|c| *c == tricks::play_card(*play))
+                    .unwrap();
+                hand.remove(card_index);
+                self.current_trick[index] = Some(*play);
+                self.plays_made += 1;
+
+                // Add the play to everyone's history. No-one has options until the next player
+                // is broadcast.
+                for (_, history) in players.iter_mut() {
+                    let plays_history = unwrap_plays_history(history);
+                    plays_history.current_trick[index] = Some(*play);
+                    plays_history.hand_sizes[index] -= 1;
+                    plays_history.play_options = None;
+                }
+                self.update_open_hand(players);
+
+                // Without trumps, playing the joker reveals the suit nominated for it.
+                if let (Play::Joker(suit), BidSuit::NoTrumps) = (play, self.trumps) {
+                    info!("[client {}] nominated the joker as {:?}.", client_id, suit);
+                    for (id, history) in players.iter_mut() {
+                        unwrap_plays_history(history).joker_suit = Some(*suit);
+                        clients.send_event(
+                            id,
+                            history.clone(),
+                            api::CurrentState::JokerSuitAnnounced,
+                        );
+                    }
+                    clients.send_spectators(players, api::CurrentState::JokerSuitAnnounced);
+                }
+
+                // Playing the called card reveals the bidder's partner.
+                if Some(tricks::play_card(*play)) == self.called_card {
+                    for (_, history) in players.iter_mut() {
+                        unwrap_plays_history(history).partner_index = self.partner_index;
+                    }
+                }
+
+                // The trick is complete.
+                if self.plays_made == self.active_player_count() {
+                    let winner_index = tricks::trick_winner(
+                        self.trumps,
+                        self.led_suit().unwrap(),
+                        &self.current_trick,
+                    );
+                    let played_trick = api::PlayedTrick {
+                        leader_index: self.leader_index,
+                        plays: self.current_trick.clone(),
+                        winner_index,
+                    };
+                    self.player_tricks[winner_index] += 1;
+                    self.tricks_played += 1;
+                    self.leader_index = winner_index;
+                    self.plays_made = 0;
+
+                    let player_teams = players
+                        .iter()
+                        .map(|(_, history)| team_index(history))
+                        .collect::<Vec<_>>();
+                    let trick = std::mem::replace(
+                        &mut self.current_trick,
+                        vec![None; self.rules.player_count],
+                    );
+                    for (j, (id, history)) in players.iter_mut().enumerate() {
+                        let team = player_teams[j];
+                        let your_tricks =
+                            scoring::team_tricks(team, &player_teams, &self.player_tricks);
+                        let plays_history = unwrap_plays_history(history);
+                        plays_history.your_tricks_count = your_tricks;
+                        plays_history.their_tricks_count = self.tricks_played - your_tricks;
+                        plays_history.previous_trick = Some(trick.clone());
+                        plays_history.previous_trick_winner = Some(winner_index);
+                        plays_history.played_tricks.push(played_trick.clone());
+                        plays_history.current_trick = vec![None; self.rules.player_count];
+                        plays_history.currently_playing_player_index = winner_index;
+
+                        clients.send_event(id, history.clone(), api::CurrentState::TrickWon);
+                    }
+                    clients.send_spectators(players, api::CurrentState::TrickWon);
+
+                    // The hand is over, possibly because a misere bidder has won a trick.
+                    if self.tricks_played == 10 || self.misere_bidder_index == Some(winner_index) {
+                        info!("Hand finished.");
+                        super::start_turn(players, None);
+                        return game_won::end_game(
+                            players,
+                            clients,
+                            self.game_index,
+                            &self.player_tricks,
+                            self.partner_index,
+                            self.rules,
+                            self.deck,
+                        );
+                    }
+                }
+
+                // Play is ongoing; broadcast the next player and their options.
+                let next_index = self.current_player_index();
+                self.update_play_options(players);
+                self.turn_deadline = super::start_turn(players, self.rules.play_timeout_secs);
+                for (j, (id, history)) in players.iter_mut().enumerate() {
+                    unwrap_plays_history(history).currently_playing_player_index = next_index;
+                    clients.send_event(id, history.clone(), self.waiting_state(j));
+                }
+                clients.send_spectators(players, self.waiting_state(0));
+            }
+
+            _bad_step => {
+                super::process_bad_step(
+                    players,
+                    player_index,
+                    clients,
+                    client_id,
+                    step,
+                    self.waiting_state(index),
+                    "during play",
+                );
+            }
+        }
+
+        self
+    }
+
+    fn waiting_state(&self, player_index: usize) -> api::CurrentState {
+        if player_index == self.current_player_index() {
+            api::CurrentState::WaitingForYourPlay
+        } else {
+            api::CurrentState::WaitingForTheirPlay
+        }
+    }
+
+    fn turn_deadline(&self) -> Option<time::Instant> {
+        self.turn_deadline
+    }
+
+    // A player who runs out of time plays their lowest legal card.
+    fn default_step(
+        &self,
+        players: &[(events::ClientId, api::History)],
+    ) -> Option<(usize, api::Step)> {
+        let index = self.current_player_index();
+        let game_history = players[index].1.game_history.as_ref()?;
+        let play = game_history
+            .plays_history
+            .as_ref()?
+            .play_options
+            .as_ref()?
+            .iter()
+            .copied()
+            .min_by_key(|&play| tricks::play_strength(self.trumps, play))?;
+
+        Some((index, api::Step::MakePlay(play)))
+    }
+}
+
+// Returns the index of the team the given player belongs to.
+fn team_index(history: &api::History) -> usize {
+    // Invariant: all players have lobby history populated.
+    history.lobby_history.as_ref().unwrap().your_team_index
+}
+
+// Convenience functions to extract mutable sub-histories.
+fn unwrap_game_history(history: &mut api::History) -> &mut api::GameHistory {
+    history.game_history.as_mut().unwrap()
+}
+
+fn unwrap_plays_history(history: &mut api::History) -> &mut api::PlaysHistory {
+    unwrap_game_history(history).plays_history.as_mut().unwrap()
+}
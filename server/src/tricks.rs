@@ -0,0 +1,305 @@
+// The rules for ranking the cards played to a trick. Cards are ranked relative to the trump suit
+// of the contract and the suit that was led:
+//
+//   1) The joker is the highest trump. Without trumps it beats every other card, taking whichever
+//      suit has been nominated for it.
+//   2) The jack of trumps (the right bower) is the next-highest trump.
+//   3) The jack of the other suit of the same colour (the left bower) is the third-highest trump,
+//      and counts as a trump rather than a card of its printed suit.
+//   4) The remaining trumps rank by face, and all trumps beat all non-trumps.
+//   5) Cards of the led suit rank by face, and any other card can't win the trick.
+
+use crate::types::*;
+
+// Returns the trump suit for the given bid. Miseres are played without trumps.
+pub fn bid_trumps(bid: Bid) -> BidSuit {
+    match bid {
+        Bid::Tricks(_, suit) => suit,
+        _ => BidSuit::NoTrumps,
+    }
+}
+
+// Returns the other suit of the same colour as the given suit.
+pub fn same_colour_suit(suit: Suit) -> Suit {
+    match suit {
+        Suit::Spades => Suit::Clubs,
+        Suit::Clubs => Suit::Spades,
+        Suit::Diamonds => Suit::Hearts,
+        Suit::Hearts => Suit::Diamonds,
+    }
+}
+
+// Returns the suit that the given card belongs to for the purposes of play. This differs from the
+// printed suit for the left bower, which belongs to the trump suit.
+pub fn effective_suit(trumps: BidSuit, play: Play) -> Suit {
+    match (play, trumps) {
+        (Play::Joker(suit), _) => suit,
+        (Play::SuitedCard(card), BidSuit::Suit(trumps))
+            if card.face == JACK && card.suit == same_colour_suit(trumps) =>
+        {
+            trumps
+        }
+        (Play::SuitedCard(card), _) => card.suit,
+    }
+}
+
+// Returns the strength of the given play, where stronger plays beat weaker plays. Returns None for
+// plays that can't win the trick (i.e. that are neither trumps nor of the led suit).
+pub fn play_rank(trumps: BidSuit, led_suit: Suit, play: Play) -> Option<usize> {
+    // Leaves room for all faces beneath the bowers and the joker.
+    const TRUMP_BASE: usize = 100;
+
+    let Play::SuitedCard(card) = play else {
+        return Some(TRUMP_BASE + 3 * JACK);
+    };
+
+    match trumps {
+        BidSuit::Suit(trumps) if card.face == JACK && card.suit == trumps => {
+            Some(TRUMP_BASE + 2 * JACK)
+        }
+        BidSuit::Suit(trumps) if effective_suit(BidSuit::Suit(trumps), play) == trumps => {
+            // Left bower if it isn't of its printed suit.
+            if card.suit != trumps {
+                Some(TRUMP_BASE + 2 * JACK - 1)
+            } else {
+                Some(TRUMP_BASE + card.face)
+            }
+        }
+        _ if card.suit == led_suit => Some(card.face),
+        _ => None,
+    }
+}
+
+// Returns the play the given card makes when it is led. Without trumps, the joker is treated as if
+// it were nominated as spades.
+pub fn card_play(trumps: BidSuit, card: Card) -> Play {
+    match (card, trumps) {
+        (Card::SuitedCard(card), _) => Play::SuitedCard(card),
+        (Card::Joker, BidSuit::Suit(suit)) => Play::Joker(suit),
+        (Card::Joker, BidSuit::NoTrumps) => Play::Joker(Suit::Spades),
+    }
+}
+
+// Returns the card in hand that corresponds to the given play.
+pub fn play_card(play: Play) -> Card {
+    match play {
+        Play::SuitedCard(card) => Card::SuitedCard(card),
+        Play::Joker(_) => Card::Joker,
+    }
+}
+
+// Returns the strength of the given play on its own (i.e. as if its suit had been led). Used to
+// find the lowest cards in a hand.
+pub fn play_strength(trumps: BidSuit, play: Play) -> usize {
+    // Invariant: every play can win a trick of its own suit.
+    play_rank(trumps, effective_suit(trumps, play), play).unwrap()
+}
+
+// Returns the index of the play that won the given trick. The trick contains an entry for each
+// player, which is None for players who didn't play (e.g. a sitting-out partner in misere).
+pub fn trick_winner(trumps: BidSuit, led_suit: Suit, trick: &[Option<Play>]) -> usize {
+    debug_assert!(trick.iter().any(|p| p.is_some()));
+
+    (0..trick.len())
+        .max_by_key(|&i| trick[i].and_then(|p| play_rank(trumps, led_suit, p)))
+        .unwrap()
+}
+
+// Returns the plays that can be made from the given hand, given the suit led to the ongoing trick
+// (if it has been led). Players must follow the led suit if they can, where the left bower and the
+// joker belong to the trump suit.
+//
+// Without trumps, the joker's suit is nominated when it is played: it takes the led suit, or any
+// suit when leading. If void_only_joker is set, it can only be played to a trick if its holder
+// can't otherwise follow suit.
+pub fn legal_plays(
+    trumps: BidSuit,
+    void_only_joker: bool,
+    hand: &[Card],
+    led_suit: Option<Suit>,
+) -> Vec<Play> {
+    let fixed_joker_suit = match trumps {
+        BidSuit::Suit(trumps) => Some(trumps),
+        BidSuit::NoTrumps => None,
+    };
+
+    // The plays each card can be used for, irrespective of the led suit.
+    let all_plays = hand.iter().flat_map(|card| match (card, fixed_joker_suit) {
+        (Card::SuitedCard(card), _) => vec![Play::SuitedCard(*card)],
+        (Card::Joker, Some(suit)) => vec![Play::Joker(suit)],
+        (Card::Joker, None) => match led_suit {
+            Some(suit) => vec![Play::Joker(suit)],
+            None => [Suit::Spades, Suit::Clubs, Suit::Diamonds, Suit::Hearts]
+                .iter()
+                .map(|suit| Play::Joker(*suit))
+                .collect(),
+        },
+    });
+
+    let Some(led_suit) = led_suit else {
+        return all_plays.collect();
+    };
+
+    // Plays that follow the led suit. A restricted joker without trumps never follows suit.
+    let following = all_plays
+        .clone()
+        .filter(|play| match play {
+            Play::Joker(_) if fixed_joker_suit.is_none() => !void_only_joker,
+            _ => effective_suit(trumps, *play) == led_suit,
+        })
+        .collect::<Vec<_>>();
+
+    if following.is_empty() {
+        all_plays.collect()
+    } else {
+        following
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(face: usize, suit: Suit) -> Play {
+        Play::SuitedCard(SuitedCard { face, suit })
+    }
+
+    const HEARTS: BidSuit = BidSuit::Suit(Suit::Hearts);
+
+    #[test]
+    fn bowers_rank_beneath_the_joker() {
+        let rank = |p| play_rank(HEARTS, Suit::Spades, p);
+        let joker = rank(Play::Joker(Suit::Hearts));
+        let right_bower = rank(play(JACK, Suit::Hearts));
+        let left_bower = rank(play(JACK, Suit::Diamonds));
+        let ace_of_trumps = rank(play(ACE, Suit::Hearts));
+
+        assert!(joker > right_bower);
+        assert!(right_bower > left_bower);
+        assert!(left_bower > ace_of_trumps);
+        assert!(ace_of_trumps > rank(play(ACE, Suit::Spades)));
+    }
+
+    #[test]
+    fn left_bower_is_a_trump() {
+        assert_eq!(
+            effective_suit(HEARTS, play(JACK, Suit::Diamonds)),
+            Suit::Hearts
+        );
+        assert_eq!(effective_suit(HEARTS, play(JACK, Suit::Clubs)), Suit::Clubs);
+        assert_eq!(
+            effective_suit(BidSuit::NoTrumps, play(JACK, Suit::Diamonds)),
+            Suit::Diamonds
+        );
+    }
+
+    #[test]
+    fn cards_off_the_led_suit_cannot_win() {
+        assert_eq!(
+            play_rank(HEARTS, Suit::Spades, play(ACE, Suit::Clubs)),
+            None
+        );
+
+        // The led five of spades beats the ace of clubs, but not the four of hearts.
+        let trick = [
+            Some(play(5, Suit::Spades)),
+            Some(play(ACE, Suit::Clubs)),
+            None,
+            Some(play(4, Suit::Hearts)),
+        ];
+        assert_eq!(trick_winner(HEARTS, Suit::Spades, &trick), 3);
+        assert_eq!(trick_winner(BidSuit::NoTrumps, Suit::Spades, &trick), 0);
+    }
+
+    #[test]
+    fn joker_wins_without_trumps() {
+        let trick = [
+            Some(play(ACE, Suit::Spades)),
+            Some(Play::Joker(Suit::Spades)),
+            Some(play(JACK, Suit::Spades)),
+        ];
+        assert_eq!(trick_winner(BidSuit::NoTrumps, Suit::Spades, &trick), 1);
+    }
+
+    fn card(face: usize, suit: Suit) -> Card {
+        Card::SuitedCard(SuitedCard { face, suit })
+    }
+
+    #[test]
+    fn left_bower_follows_trumps() {
+        let hand = [
+            card(JACK, Suit::Diamonds),
+            card(5, Suit::Diamonds),
+            card(9, Suit::Spades),
+        ];
+
+        // The left bower must be played to a trump lead.
+        assert_eq!(
+            legal_plays(HEARTS, true, &hand, Some(Suit::Hearts)),
+            vec![play(JACK, Suit::Diamonds)]
+        );
+
+        // It can't be played to a lead of its printed suit while another card of that suit is
+        // held.
+        assert_eq!(
+            legal_plays(HEARTS, true, &hand, Some(Suit::Diamonds)),
+            vec![play(5, Suit::Diamonds)]
+        );
+    }
+
+    #[test]
+    fn void_players_can_play_anything() {
+        let hand = [
+            card(JACK, Suit::Diamonds),
+            card(9, Suit::Spades),
+            Card::Joker,
+        ];
+        assert_eq!(legal_plays(HEARTS, true, &hand, Some(Suit::Clubs)).len(), 3);
+    }
+
+    #[test]
+    fn joker_is_a_trump() {
+        let hand = [card(9, Suit::Hearts), Card::Joker, card(9, Suit::Spades)];
+        assert_eq!(
+            legal_plays(HEARTS, true, &hand, Some(Suit::Hearts)),
+            vec![play(9, Suit::Hearts), Play::Joker(Suit::Hearts)]
+        );
+        assert_eq!(
+            legal_plays(HEARTS, true, &hand, None),
+            vec![
+                play(9, Suit::Hearts),
+                Play::Joker(Suit::Hearts),
+                play(9, Suit::Spades)
+            ]
+        );
+    }
+
+    #[test]
+    fn joker_is_nominated_without_trumps() {
+        let hand = [card(9, Suit::Spades), Card::Joker];
+
+        // A led joker can be nominated as any suit.
+        let leads = legal_plays(BidSuit::NoTrumps, true, &hand, None);
+        assert_eq!(leads.len(), 5);
+        assert!(leads.contains(&Play::Joker(Suit::Clubs)));
+
+        // Otherwise it takes the led suit.
+        assert_eq!(
+            legal_plays(BidSuit::NoTrumps, true, &hand, Some(Suit::Hearts)),
+            vec![play(9, Suit::Spades), Play::Joker(Suit::Hearts)]
+        );
+    }
+
+    #[test]
+    fn void_only_joker_cannot_follow_suit() {
+        let hand = [card(9, Suit::Spades), Card::Joker];
+        assert_eq!(
+            legal_plays(BidSuit::NoTrumps, true, &hand, Some(Suit::Spades)),
+            vec![play(9, Suit::Spades)]
+        );
+        assert_eq!(
+            legal_plays(BidSuit::NoTrumps, false, &hand, Some(Suit::Spades)),
+            vec![play(9, Suit::Spades), Play::Joker(Suit::Spades)]
+        );
+    }
+}
@@ -1,5 +1,8 @@
 // Datatypes used in the server and server API.
 
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
@@ -24,9 +27,15 @@ pub enum Bid {
     Pass,
 }
 
+// The face values of the jack and ace, with the queen and king between them. Numbered cards take
+// their own value, which extends up to 13 for larger decks.
+pub const JACK: usize = 14;
+pub const ACE: usize = 17;
+
 #[derive(Clone, Debug, Copy, Eq, Hash, PartialEq, Serialize, Deserialize)]
 pub struct SuitedCard {
-    // The number or face on the card. Invariant: in [4..14], with ace represented by 14.
+    // The number or face on the card. Invariant: in [2..17], with jack to ace represented by 14 to
+    // 17.
     pub face: usize,
     pub suit: Suit,
 }
@@ -37,9 +46,370 @@ pub enum Card {
     Joker,
 }
 
+// A quick reaction that can be sent to the table instead of a chat message.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Reaction {
+    Nice,
+    Oops,
+    Gg,
+}
+
+// How well a computer player plays. Basic bots play by rules of thumb. The others search for their
+// best move by simulating deals consistent with what they have seen, and simulate more deals on
+// harder difficulties.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum BotDifficulty {
+    Basic,
+    Easy,
+    Medium,
+    Hard,
+}
+
+// What happens when every player passes without bidding.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum AllPassRule {
+    // The hand is thrown in and the next dealer deals again.
+    Redeal,
+
+    // The hand is played at no trumps without the kitty, and every trick is worth 10 points to the
+    // team that wins it.
+    PlayNoTrumps,
+}
+
+// When players may bid misere and open misere.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum MisereRule {
+    // Miseres can't be bid.
+    Disabled,
+
+    // Miseres can only be bid once every player has had a chance to bid.
+    AfterAllBid,
+
+    // Miseres can be bid at any point in the bidding.
+    Anytime,
+}
+
+// Where misere sits among the eight-trick bids. Open misere is always the highest bid.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum MisereRank {
+    // Between eight spades and eight clubs, in line with its value of 250 points.
+    AfterEightSpades,
+
+    // Between eight clubs and eight diamonds.
+    AfterEightClubs,
+}
+
+// The house rules a session is played under. Chosen when the session is created, and fixed for
+// the whole match. Any rules missing from a serialized config take their default values.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Rules {
+    // The number of seats at the table, which also determines the deck. Invariant: in [3..6].
+    pub player_count: usize,
+
+    pub misere_rule: MisereRule,
+    pub misere_rank: MisereRank,
+
+    // Whether a player who has passed may bid again later in the bidding.
+    pub passed_players_reenter: bool,
+
+    // The score a team must reach (by making a contract) to win the match. A team whose score
+    // falls to the negative of this loses the match. Invariant: positive.
+    pub target_score: isize,
+
+    pub all_pass_rule: AllPassRule,
+
+    // Whether, without trumps (i.e. in no trumps and miseres, and in a hand played after every
+    // player passed), the joker can only be played to a trick when its holder can't follow the led
+    // suit.
+    pub no_trumps_joker_void_only: bool,
+
+    // Whether a team that bids fewer than 250 points and wins every trick scores 250 points instead.
+    pub slam_bonus: bool,
+
+    // How long (in seconds) the table waits for a disconnected player to resume their seat before
+    // the match is aborted. Invariant: at most MAX_WAIT_SECS.
+    pub reconnect_grace_secs: u64,
+
+    // How long (in seconds) a player has to bid, to use the kitty (and call a partner), and to play
+    // a card. When a turn runs out, a default action is taken for the
+    // player. Turns without a limit are untimed. Invariant: at most MAX_WAIT_SECS.
+    pub bid_timeout_secs: Option<u64>,
+    pub kitty_timeout_secs: Option<u64>,
+    pub play_timeout_secs: Option<u64>,
+}
+
+// The longest (in seconds) the table can wait on a turn or a disconnected player: one day.
+const MAX_WAIT_SECS: u64 = 24 * 60 * 60;
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            player_count: 4,
+            misere_rule: MisereRule::AfterAllBid,
+            misere_rank: MisereRank::AfterEightSpades,
+            passed_players_reenter: false,
+            target_score: 500,
+            all_pass_rule: AllPassRule::Redeal,
+            no_trumps_joker_void_only: true,
+            slam_bonus: true,
+            reconnect_grace_secs: 60,
+            bid_timeout_secs: None,
+            kitty_timeout_secs: None,
+            play_timeout_secs: None,
+        }
+    }
+}
+
+impl Rules {
+    // Returns the number of teams. Four players play in two fixed partnerships and six players in
+    // three. Otherwise, every player is their own team.
+    pub fn team_count(&self) -> usize {
+        match self.player_count {
+            4 => 2,
+            6 => 3,
+            n => n,
+        }
+    }
+
+    // Returns the team that the player in the given seat belongs to. Partners sit opposite each
+    // other.
+    pub fn team_index(&self, player_index: usize) -> usize {
+        player_index % self.team_count()
+    }
+
+    // Returns why a table can't be played under these rules, if it can't.
+    pub fn validate(&self) -> Result<(), String> {
+        if deck_cards(self.player_count).is_none() {
+            return Err(format!(
+                "There's no deck for {} players.",
+                self.player_count
+            ));
+        }
+
+        if self.target_score <= 0 {
+            return Err("The target score must be positive.".to_string());
+        }
+
+        let waits = [
+            Some(self.reconnect_grace_secs),
+            self.bid_timeout_secs,
+            self.kitty_timeout_secs,
+            self.play_timeout_secs,
+        ];
+        if waits.into_iter().flatten().any(|secs| secs > MAX_WAIT_SECS) {
+            return Err(format!(
+                "Turns and reconnections can't be waited on for more than {} seconds.",
+                MAX_WAIT_SECS
+            ));
+        }
+
+        Ok(())
+    }
+
+    // Returns the cards in the deck the table is dealt from. Invariant: the rules are valid.
+    pub fn deck_cards(&self) -> Vec<Card> {
+        deck_cards(self.player_count).unwrap()
+    }
+
+    // Returns whether the winning bidder calls a partner by naming a card, as in five-handed play.
+    pub fn called_partner(&self) -> bool {
+        self.player_count == 5
+    }
+}
+
 // A card played on a turn. The joker is assigned its effective suit.
-#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq, Serialize, Deserialize)]
 pub enum Play {
     SuitedCard(SuitedCard),
     Joker(Suit),
 }
+
+// The cards dealt for one game.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Deal {
+    pub hands: Vec<Vec<Card>>, // Invariant: one per player, each of length 10.
+    pub kitty: Vec<Card>,      // Invariant: length of 3.
+}
+
+// The deck that every game in a match is dealt from. Deals are reproducible: a deck shuffled from a
+// given seed produces the same sequence of deals, and a deck with a fixed order always produces the
+// same deal.
+#[derive(Clone, Debug)]
+pub struct Deck {
+    // The order of the cards before shuffling.
+    cards: Vec<Card>,
+
+    // The shuffler, or None if the deck is never shuffled.
+    rng: Option<StdRng>,
+}
+
+impl Deck {
+    // Returns a deck for the table that is shuffled before each deal by a generator seeded with the
+    // given value.
+    pub fn from_seed(seed: u64, rules: Rules) -> Self {
+        Deck {
+            cards: rules.deck_cards(),
+            rng: Some(StdRng::seed_from_u64(seed)),
+        }
+    }
+
+    // Returns a deck that is always dealt in the given order, or why it can't be. The cards must be
+    // exactly those of the deck for the given number of players. Hands are dealt in consecutive
+    // runs of ten cards, and the last three cards form the kitty.
+    pub fn fixed(cards: Vec<Card>, player_count: usize) -> Result<Self, String> {
+        let Some(expected) = deck_cards(player_count) else {
+            return Err(format!("There's no deck for {} players.", player_count));
+        };
+        if cards.len() != expected.len() {
+            return Err(format!(
+                "The deck for {} players has {} cards, not {}.",
+                player_count,
+                expected.len(),
+                cards.len()
+            ));
+        }
+
+        // With the right number of cards, any repeated card leaves another card missing.
+        let missing = expected
+            .iter()
+            .filter(|card| !cards.contains(card))
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            return Err(format!(
+                "The deck for {} players is missing {:?}.",
+                player_count, missing
+            ));
+        }
+
+        Ok(Deck { cards, rng: None })
+    }
+
+    // Shuffles (if necessary) and deals the cards for the next game.
+    pub fn deal(&mut self) -> Deal {
+        if let Some(rng) = self.rng.as_mut() {
+            self.cards.shuffle(rng);
+        }
+
+        let (hands, kitty) = self.cards.split_at(self.cards.len() - 3);
+        Deal {
+            hands: hands.chunks(10).map(|h| h.to_vec()).collect(),
+            kitty: kitty.to_vec(),
+        }
+    }
+}
+
+// Returns the cards in the deck for the given number of players. Every deck holds ten cards per
+// player, three for the kitty and the joker:
+//
+//   3 players: 7s to aces (33 cards).
+//   4 players: 5s to aces, and the red 4s (43 cards).
+//   5 players: 4s to aces (including 11s and 12s) without the black 4s, and the red 13s (53 cards).
+//   6 players: 2s to aces (including 11s and 12s), and the red 13s (63 cards).
+//
+// Returns None if there is no deck for that many players.
+pub fn deck_cards(player_count: usize) -> Option<Vec<Card>> {
+    fn red(card: &SuitedCard) -> bool {
+        matches!(card.suit, Suit::Diamonds | Suit::Hearts)
+    }
+
+    let included: fn(&SuitedCard) -> bool = match player_count {
+        3 => |card| (7..=10).contains(&card.face) || card.face >= JACK,
+        4 => |card| {
+            (5..=10).contains(&card.face) || card.face >= JACK || (card.face == 4 && red(card))
+        },
+        5 => |card| {
+            (5..=12).contains(&card.face)
+                || card.face >= JACK
+                || (matches!(card.face, 4 | 13) && red(card))
+        },
+        6 => |card| card.face != 13 || red(card),
+        _ => return None,
+    };
+
+    let cards = (2..=ACE)
+        .flat_map(|face| {
+            [Suit::Spades, Suit::Clubs, Suit::Diamonds, Suit::Hearts]
+                .iter()
+                .map(move |suit| SuitedCard { face, suit: *suit })
+        })
+        .filter(included)
+        .map(Card::SuitedCard)
+        .chain([Card::Joker])
+        .collect();
+
+    Some(cards)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeded_decks_deal_the_same_games() {
+        let mut a = Deck::from_seed(500, Rules::default());
+        let mut b = Deck::from_seed(500, Rules::default());
+        for _ in 0..3 {
+            assert_eq!(a.deal(), b.deal());
+        }
+    }
+
+    #[test]
+    fn fixed_deck_deals_in_order() {
+        let cards = deck_cards(4).unwrap();
+        let deal = Deck::fixed(cards.clone(), 4).unwrap().deal();
+        assert_eq!(deal.hands.len(), 4);
+        assert_eq!(deal.hands[1], cards[10..20]);
+        assert_eq!(deal.kitty, cards[40..]);
+    }
+
+    #[test]
+    fn fixed_deck_rejects_wrong_cards() {
+        let mut cards = deck_cards(4).unwrap();
+        assert!(Deck::fixed(cards.clone(), 5).is_err());
+        assert!(Deck::fixed(cards.clone(), 7).is_err());
+        assert!(Deck::fixed(cards[..2].to_vec(), 4).is_err());
+
+        cards[0] = cards[1];
+        assert!(Deck::fixed(cards, 4).is_err());
+    }
+
+    #[test]
+    fn decks_exist_for_three_to_six_players() {
+        for player_count in 3..=6 {
+            let cards = deck_cards(player_count).unwrap();
+            assert_eq!(cards.len(), 10 * player_count + 3);
+        }
+        assert_eq!(deck_cards(2), None);
+        assert_eq!(deck_cards(7), None);
+    }
+
+    #[test]
+    fn rules_bound_targets_and_waits() {
+        assert!(Rules::default().validate().is_ok());
+
+        for target_score in [0, -500, isize::MIN] {
+            let rules = Rules {
+                target_score,
+                ..Rules::default()
+            };
+            assert!(rules.validate().is_err());
+        }
+
+        let rules = Rules {
+            reconnect_grace_secs: u64::MAX,
+            ..Rules::default()
+        };
+        assert!(rules.validate().is_err());
+        let rules = Rules {
+            play_timeout_secs: Some(MAX_WAIT_SECS + 1),
+            ..Rules::default()
+        };
+        assert!(rules.validate().is_err());
+        let rules = Rules {
+            bid_timeout_secs: Some(MAX_WAIT_SECS),
+            ..Rules::default()
+        };
+        assert!(rules.validate().is_ok());
+    }
+}
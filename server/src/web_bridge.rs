@@ -36,7 +36,7 @@ pub fn connect_bridge(addr: String) -> events::ClientEventReceiver {
             };
 
             // Guaranteed to be unique amongst all threads.
-            let client_id = pretty_num(RandomGenerator::default().next_id());
+            let client_id = pretty_num(RandomGenerator.next_id());
             info!(
                 "[client {}] connected to TCP stream at {}.",
                 client_id, &client_addr
@@ -44,7 +44,10 @@ pub fn connect_bridge(addr: String) -> events::ClientEventReceiver {
 
             // Establish the WebSocket connection.
             let Ok(websocket) = tokio_ws2::accept_async(stream).await else {
-                error!("[client {}] couldn't establish WebSocket connection with {}.", client_id, &client_addr);
+                error!(
+                    "[client {}] couldn't establish WebSocket connection with {}.",
+                    client_id, &client_addr
+                );
                 continue;
             };
             info!(